@@ -0,0 +1,131 @@
+use crate::{
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    sampler::hash,
+    texture::Texture,
+};
+
+/// How the opacity of a hit decides whether the ray stops there.
+pub enum AlphaTest {
+    /// Opaque where the alpha is at least the threshold, a hole elsewhere.
+    Threshold(f64),
+    /// Opaque with probability equal to the alpha, giving soft edges on average.
+    /// The choice is a hash of the ray and hit distance, so it repeats exactly
+    /// between renders.
+    Stochastic,
+}
+
+/// Wraps a hittable with an opacity texture. Hits that fail the alpha test are
+/// skipped and the search continues further along the ray, so foliage cards and
+/// fences can be cut out of single primitives.
+pub struct AlphaMask<'a> {
+    object: Box<dyn Hittable + 'a>,
+    alpha: Box<dyn Texture + 'a>,
+    test: AlphaTest,
+}
+
+impl<'a> AlphaMask<'a> {
+    /// The alpha is the luminance of the texture value at the hit.
+    pub fn new(
+        object: impl Hittable + 'a,
+        alpha: impl Texture + 'a,
+        test: AlphaTest,
+    ) -> AlphaMask<'a> {
+        AlphaMask {
+            object: Box::new(object),
+            alpha: Box::new(alpha),
+            test,
+        }
+    }
+
+    fn is_opaque(&self, r: &Ray, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.value(rec.u, rec.v, rec.p).luminance();
+        match self.test {
+            AlphaTest::Threshold(threshold) => alpha >= threshold,
+            AlphaTest::Stochastic => {
+                let key = [
+                    r.origin.x,
+                    r.origin.y,
+                    r.origin.z,
+                    r.direction.x,
+                    r.direction.y,
+                    r.direction.z,
+                    rec.t,
+                ];
+                let u = (hash(&key.map(f64::to_bits)) >> 11) as f64 / (1u64 << 53) as f64;
+                u < alpha
+            }
+        }
+    }
+}
+
+impl<'a> Hittable for AlphaMask<'a> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let mut t_min = ray_t.min;
+        loop {
+            let rec = self.object.hit(r, Interval::new(t_min, ray_t.max))?;
+            if self.is_opaque(r, &rec) {
+                return Some(rec);
+            }
+            t_min = rec.t;
+        }
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.object.materials()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        alpha_mask::{AlphaMask, AlphaTest},
+        color::Color,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        interval::Interval,
+        material::Lambertian,
+        quad::Quad,
+        ray::Ray,
+        sphere::Sphere,
+        texture::SolidColor,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn test_rays_pass_where_alpha_is_zero() {
+        // A masked sphere at z = -2 in front of a wall at z = -5.
+        let distance = |alpha: f64, test: AlphaTest| {
+            let grey = || Lambertian::new(Color::new(0.5, 0.5, 0.5));
+            let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, grey());
+            let mut world = HittableList::new();
+            world.add(AlphaMask::new(
+                sphere,
+                SolidColor::new(Color::new(alpha, alpha, alpha)),
+                test,
+            ));
+            let corner = Point3::new(-5.0, -5.0, -5.0);
+            world.add(Quad::new(
+                corner,
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 10.0, 0.0),
+                grey(),
+            ));
+
+            let r = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+            world
+                .hit(&r, Interval::new(0.001, f64::INFINITY))
+                .unwrap()
+                .t
+        };
+
+        // Both the front and the back of the sphere are cut out.
+        assert_eq!(5.0, distance(0.0, AlphaTest::Threshold(0.5)));
+        assert_eq!(5.0, distance(0.0, AlphaTest::Stochastic));
+        assert_eq!(5.0, distance(0.4, AlphaTest::Threshold(0.5)));
+        assert_eq!(1.5, distance(0.6, AlphaTest::Threshold(0.5)));
+        assert_eq!(1.5, distance(1.0, AlphaTest::Stochastic));
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+use crate::{
+    color::Color,
+    film::{read_u64, write_u64},
+    vec3::Vec3,
+};
+
+/// What the arbitrary output variables record about one camera sample.
+pub struct SampleAovs {
+    pub first_hit: Option<FirstHit>,
+    pub lights: Vec<Color>, // Radiance reaching the camera from each light group
+}
+
+/// The surface a camera ray hits first.
+pub struct FirstHit {
+    pub depth: f64, // Distance from the ray origin
+    pub normal: Vec3,
+    pub position: Vec3,
+    pub albedo: Color,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl SampleAovs {
+    pub fn new(light_groups: usize) -> SampleAovs {
+        SampleAovs {
+            first_hit: None,
+            lights: vec![Color::default(); light_groups],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.first_hit = None;
+        self.lights.fill(Color::default());
+    }
+}
+
+/// Filter-weighted sums of the sample AOVs of a pixel. The surface quantities are
+/// averaged over the samples that hit something, while the light groups are
+/// averaged over every sample, like the pixel colour they add up to.
+#[derive(Clone, Default)]
+pub struct AovSums {
+    pub hit_weight: f64,
+    pub depth: f64,
+    pub normal: Vec3,
+    pub position: Vec3,
+    pub albedo: Color,
+    pub lights: Vec<Color>,
+    // IDs cannot be averaged, so they come from the first sample with a hit; 0 is
+    // the background.
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl AovSums {
+    pub fn new(light_groups: usize) -> AovSums {
+        AovSums {
+            lights: vec![Color::default(); light_groups],
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, sample: &SampleAovs, weight: f64) {
+        for (sum, light) in self.lights.iter_mut().zip(&sample.lights) {
+            *sum += weight * light.clone();
+        }
+        let Some(hit) = &sample.first_hit else {
+            return;
+        };
+        self.hit_weight += weight;
+        self.depth += weight * hit.depth;
+        self.normal += weight * hit.normal;
+        self.position += weight * hit.position;
+        self.albedo += weight * hit.albedo.clone();
+        if self.object_id == 0 {
+            self.object_id = hit.object_id;
+            self.material_id = hit.material_id;
+        }
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let albedo = Vec3::from(self.albedo.clone());
+        let mut values = vec![self.hit_weight, self.depth];
+        for v in [self.normal, self.position, albedo] {
+            values.extend([v.x, v.y, v.z]);
+        }
+        for light in &self.lights {
+            let light = Vec3::from(light.clone());
+            values.extend([light.x, light.y, light.z]);
+        }
+        write_u64(out, self.lights.len() as u64)?;
+        for value in values {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        write_u64(out, self.object_id as u64)?;
+        write_u64(out, self.material_id as u64)
+    }
+
+    /// Read sums written by `write_to`, which must have the given number of light
+    /// groups.
+    pub fn read_from(input: &mut impl Read, light_groups: usize) -> io::Result<AovSums> {
+        if read_u64(input)? != light_groups as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "AOVs have the wrong number of light groups",
+            ));
+        }
+        let hit_weight = read_f64(input)?;
+        let depth = read_f64(input)?;
+        let normal = read_vec3(input)?;
+        let position = read_vec3(input)?;
+        let albedo = read_vec3(input)?.into();
+        let lights = (0..light_groups)
+            .map(|_| read_vec3(input).map(Color::from))
+            .collect::<io::Result<_>>()?;
+        Ok(AovSums {
+            hit_weight,
+            depth,
+            normal,
+            position,
+            albedo,
+            lights,
+            object_id: read_u64(input)? as u32,
+            material_id: read_u64(input)? as u32,
+        })
+    }
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    read_u64(input).map(f64::from_bits)
+}
+
+fn read_vec3(input: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
@@ -0,0 +1,111 @@
+use std::f64::consts::PI;
+
+use crate::{image::Image, sampler::Sampler, vec3::Vec3};
+
+/// The shape of the lens opening, which is what out-of-focus highlights take on.
+#[derive(Default)]
+pub enum Aperture {
+    /// A perfectly round opening.
+    #[default]
+    Disk,
+    /// A regular polygon formed by `blades` straight diaphragm blades, rotated
+    /// by `rotation` degrees.
+    Polygon { blades: usize, rotation: f64 },
+    /// An arbitrary shape given by the luminance of an image.
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    /// Random point on the aperture in the lens plane: within the unit disk, or
+    /// for a mask within the square \[-1, 1\]² around it.
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        match self {
+            Aperture::Disk => Vec3::sample_unit_disk(sampler.get_2d()),
+            Aperture::Polygon { blades, rotation } => {
+                // Pick one of the identical triangles fanning out from the centre,
+                // then a uniform point inside it.
+                let blades = (*blades).max(3);
+                let k = ((sampler.get_1d() * blades as f64) as usize).min(blades - 1);
+                let angle = |k: usize| rotation.to_radians() + 2.0 * PI * k as f64 / blades as f64;
+                let a = Vec3::new(angle(k).cos(), angle(k).sin(), 0.0);
+                let b = Vec3::new(angle(k + 1).cos(), angle(k + 1).sin(), 0.0);
+
+                let (u, t) = sampler.get_2d();
+                u.sqrt() * ((1.0 - t) * a + t * b)
+            }
+            Aperture::Mask(mask) => mask.sample(sampler),
+        }
+    }
+}
+
+/// An image stretched over the square around the unit disk, sampled in
+/// proportion to pixel luminance so grey pixels pass part of the light.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    /// A mask from an image, which must let some light through.
+    pub fn new(image: &Image) -> Result<ApertureMask, String> {
+        let mut total = 0.0;
+        let mut cdf = Vec::with_capacity(image.width() * image.height());
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                total += image.pixel(x, y).luminance().max(0.0);
+                cdf.push(total);
+            }
+        }
+        if total <= 0.0 {
+            return Err(String::from("Aperture mask is black"));
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        Ok(ApertureMask {
+            width: image.width(),
+            height: image.height(),
+            cdf,
+        })
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let u = sampler.get_1d();
+        let index = self.cdf.partition_point(|&c| c < u).min(self.cdf.len() - 1);
+        let (jitter_x, jitter_y) = sampler.get_2d();
+        let x = (index % self.width) as f64 + jitter_x;
+        let y = (index / self.width) as f64 + jitter_y;
+
+        Vec3::new(
+            2.0 * x / self.width as f64 - 1.0,
+            1.0 - 2.0 * y / self.height as f64,
+            0.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aperture::{Aperture, ApertureMask},
+        color::Color,
+        image::Image,
+        sampler::SamplerKind,
+    };
+
+    #[test]
+    fn test_mask_samples_only_its_lit_pixels() {
+        assert!(ApertureMask::new(&Image::new(2, 2)).is_err());
+
+        // Only the upper right quadrant of the aperture lets light through.
+        let mut image = Image::new(2, 2);
+        image.set_pixel(1, 0, Color::new(1.0, 1.0, 1.0));
+        let aperture = Aperture::Mask(ApertureMask::new(&image).unwrap());
+        let mut sampler = SamplerKind::Independent.create(64, 0);
+        for i in 0..64 {
+            sampler.start_pixel_sample(0, 0, i);
+            let p = aperture.sample(&mut *sampler);
+            assert!(p.x >= 0.0 && p.y >= 0.0 && p.length() <= 2f64.sqrt());
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::OnceLock;
+
+/// Side length of the tileable blue-noise threshold map.
+pub const TILE_SIZE: usize = 64;
+
+/// A tileable blue-noise threshold map of `TILE_SIZE`² values, each of
+/// `i / TILE_SIZE²` appearing once. Neighbouring pixels get dissimilar values,
+/// so any thresholding of the map spreads points evenly with no low-frequency clumps.
+pub fn tile() -> &'static [f64] {
+    static TILE: OnceLock<Vec<f64>> = OnceLock::new();
+    TILE.get_or_init(void_and_cluster)
+}
+
+/// Value of the blue-noise tile at (x, y), wrapping around its edges.
+pub fn value(x: u64, y: u64) -> f64 {
+    let x = x as usize % TILE_SIZE;
+    let y = y as usize % TILE_SIZE;
+    tile()[y * TILE_SIZE + x]
+}
+
+/// Ulichney's void-and-cluster method: points are ranked by repeatedly adding the
+/// point in the largest void, or removing the one in the tightest cluster, of a
+/// Gaussian-blurred binary pattern on a torus.
+fn void_and_cluster() -> Vec<f64> {
+    const N: usize = TILE_SIZE * TILE_SIZE;
+    const SIGMA: f64 = 1.5;
+
+    // Gaussian energy contribution of a point at each toroidal offset.
+    let mut kernel = vec![0.0; N];
+    for dy in 0..TILE_SIZE {
+        for dx in 0..TILE_SIZE {
+            let wrap = |d: usize| d.min(TILE_SIZE - d) as f64;
+            let d2 = wrap(dx) * wrap(dx) + wrap(dy) * wrap(dy);
+            kernel[dy * TILE_SIZE + dx] = (-d2 / (2.0 * SIGMA * SIGMA)).exp();
+        }
+    }
+    let update = |energy: &mut [f64], p: usize, sign: f64| {
+        let (px, py) = (p % TILE_SIZE, p / TILE_SIZE);
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                let dx = (x + TILE_SIZE - px) % TILE_SIZE;
+                let dy = (y + TILE_SIZE - py) % TILE_SIZE;
+                energy[y * TILE_SIZE + x] += sign * kernel[dy * TILE_SIZE + dx];
+            }
+        }
+    };
+    let extreme = |pattern: &[bool], energy: &[f64], on: bool| {
+        // Tightest cluster among set points, or largest void among unset ones.
+        let candidates = (0..N).filter(|&p| pattern[p] == on);
+        if on {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        }
+        .unwrap()
+    };
+
+    // A fixed scattering of initial points keeps the tile the same on every run.
+    let mut pattern = vec![false; N];
+    let mut energy = vec![0.0; N];
+    let mut state = 0x9e3779b97f4a7c15u64;
+    let mut ones = 0;
+    while ones < N / 10 {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let p = (state >> 33) as usize % N;
+        if !pattern[p] {
+            pattern[p] = true;
+            update(&mut energy, p, 1.0);
+            ones += 1;
+        }
+    }
+
+    // Relax the initial points into an evenly spread prototype pattern.
+    loop {
+        let cluster = extreme(&pattern, &energy, true);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = extreme(&pattern, &energy, false);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; N];
+
+    // Rank the prototype points by removing the tightest clusters first.
+    let (mut trial, mut trial_energy) = (pattern.clone(), energy.clone());
+    for r in (0..ones).rev() {
+        let cluster = extreme(&trial, &trial_energy, true);
+        trial[cluster] = false;
+        update(&mut trial_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Rank the remaining pixels by filling the largest voids.
+    for r in ones..N {
+        let void = extreme(&pattern, &energy, false);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter().map(|r| r as f64 / N as f64).collect()
+}
@@ -1,17 +1,135 @@
+use std::{
+    f64::consts::PI,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Write},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
 use crate::{
-    color::{Color, write_color},
+    aov::{AovSums, FirstHit, SampleAovs},
+    aperture::Aperture,
+    color::Color,
+    denoise::{Guides, denoise},
+    exr::Layer,
+    film::{Film, read_u64, write_u64},
+    filter::{Filter, FilterSampler},
     hittable::Hittable,
+    image::Image,
     interval::Interval,
+    lens_system::LensSystem,
+    netpbm::{NetpbmFormat, write_netpbm},
+    post::PostEffects,
     ray::Ray,
-    util::random_f64,
+    sampler::{Sampler, SamplerKind},
+    tonemap::DisplayTransform,
     vec3::{Point3, Vec3},
 };
 
+/// How directions leaving the camera are mapped onto the image.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Pinhole or thin-lens perspective with a vertical field of view of `vfov`.
+    #[default]
+    Perspective,
+    /// Parallel rays; the view covers the perspective viewport at `focus_dist`.
+    Orthographic,
+    /// Equidistant fisheye; `vfov` is the angle spanned by the image height.
+    Fisheye,
+    /// Full 360° by 180° latitude/longitude panorama, best with a 2:1 aspect ratio.
+    Equirectangular,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Projection, String> {
+        match s {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic),
+            "fisheye" => Ok(Projection::Fisheye),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            _ => Err(format!("Unknown projection: {s}")),
+        }
+    }
+}
+
+/// How the two eyes of a stereo render are laid out in the output image.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum StereoMode {
+    #[default]
+    Mono,
+    /// Left eye on the left half, right eye on the right half.
+    SideBySide,
+    /// Left eye on the top half, right eye on the bottom half.
+    OverUnder,
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<StereoMode, String> {
+        match s {
+            "mono" => Ok(StereoMode::Mono),
+            "side-by-side" => Ok(StereoMode::SideBySide),
+            "over-under" => Ok(StereoMode::OverUnder),
+            _ => Err(format!("Unknown stereo mode: {s}")),
+        }
+    }
+}
+
+/// What camera rays that miss the scene see. Unless the background is visible,
+/// they give an alpha of 0 and the image is written with coverage alpha.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    #[default]
+    Visible,
+    /// Nothing: the sky neither shows nor lights the scene.
+    Transparent,
+    /// The sky lights and is reflected in the scene but is hidden from the camera.
+    InvisibleEnvironment,
+}
+
+impl FromStr for Background {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Background, String> {
+        match s {
+            "visible" => Ok(Background::Visible),
+            "transparent" => Ok(Background::Transparent),
+            "invisible-environment" => Ok(Background::InvisibleEnvironment),
+            _ => Err(format!("Unknown background: {s}")),
+        }
+    }
+}
+
+/// A band of rows of one eye's image, the unit of work for tiled rendering.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub eye: usize,
+    pub rows: Range<u64>,
+}
+
 #[derive(Default)]
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: u64,
-    pub samples_per_pixel: usize,
+    pub samples_per_pixel: usize, // The most samples a pixel receives
+    pub min_samples_per_pixel: usize, // Samples taken before adaptive sampling may stop
+    pub adaptive_threshold: f64,  // Relative standard error at which a pixel stops, 0 to disable
+    pub progressive: bool,        // Render passes of doubling sample counts instead of one pass
+    pub time_limit: Option<Duration>, // Stop sampling when this much time has passed, after the first pass
+    pub snapshot: Option<String>,     // Netpbm file rewritten with the image after every pass
+    pub checkpoint: Option<String>,   // File the render state is saved to for `resume`
+    pub checkpoint_interval: Duration,
+    pub description: u64, // Hash of the options describing the render, checked on resume
+    pub aovs: bool,       // Record depth, normal, position, albedo, IDs and light groups
+    pub denoise: bool,    // Filter the image guided by the AOVs, which must be recorded
+    pub post: PostEffects,
+    pub display: DisplayTransform, // Exposure, tone mapping and colour spaces of the output
+    pub format: NetpbmFormat,      // Variant of the Netpbm image and snapshots written
+    pub light_groups: Vec<String>, // Names of the groups lights are counted in; 0 is the sky
+    pub sky_intensity: f64,
+    pub background: Background,
     pub max_depth: usize, // Maximum number of ray bounces into scene
     pub vfov: f64,
     pub lookfrom: Point3,
@@ -19,9 +137,29 @@ pub struct Camera {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub aperture: Aperture,
+    pub cats_eye: f64, // Off-axis clipping of the aperture, 0 for none, 1 for strong
+    // Lens shift and focal plane tilt and swing. Shift applies only to the perspective
+    // and orthographic projections, and none of them to a lens system.
+    pub shift_x: f64, // Lens shift as a fraction of the viewport width, positive to the right
+    pub shift_y: f64, // Lens shift as a fraction of the viewport height, positive upwards
+    pub tilt: f64,    // Focal plane rotation in degrees about the horizontal, top further away
+    pub swing: f64,   // Focal plane rotation in degrees about the vertical, right further away
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    pub seed: u64, // Selects the sample pattern; equal seeds give identical images
+    pub projection: Projection,
+    pub lens: Option<LensSystem>, // Replaces the projection and thin lens when set
+    pub film_diagonal: f64,       // Film size in millimetres, used with a lens system
+    pub stereo: StereoMode,
+    pub interpupillary_distance: f64,
+    pub convergence_dist: f64, // Distance at which the left and right views line up
 
+    eye_offset: f64,  // Signed distance of the current eye along u
+    films: Vec<Film>, // One per eye
+    resumed: bool,    // The films were loaded from a checkpoint
     image_height: u64,
-    pixel_samples_scale: f64,
+    filter_sampler: FilterSampler,
     center: Point3,
     pixel100_loc: Point3,
     pixel_delta_u: Vec3,
@@ -31,6 +169,8 @@ pub struct Camera {
     w: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_plane_point: Point3,
+    focus_plane_normal: Vec3,
 }
 
 impl Camera {
@@ -39,6 +179,7 @@ impl Camera {
             aspect_ratio: 1.0,
             image_width: 100,
             samples_per_pixel: 10,
+            min_samples_per_pixel: 16,
             max_depth: 10,
             vfov: 90.,
             lookfrom: Point3::new(0.0, 0.0, 0.0),
@@ -46,42 +187,423 @@ impl Camera {
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            interpupillary_distance: 0.064,
+            convergence_dist: 10.0,
+            film_diagonal: 35.0,
+            checkpoint_interval: Duration::from_secs(60),
+            light_groups: vec![String::from("sky")],
+            sky_intensity: 1.0,
             ..Default::default()
         }
     }
+    /// Render the scene, updating the snapshot file after each pass.
     pub fn render(&mut self, world: &impl Hittable) {
-        self.initialize();
+        let (snapshot, display, format) =
+            (self.snapshot.clone(), self.display.clone(), self.format);
+        self.render_progressive(world, |image, samples| {
+            let Some(path) = &snapshot else {
+                return;
+            };
+            let written = File::create(path).and_then(|file| {
+                let mut out = BufWriter::new(file);
+                write_netpbm(&mut out, image, None, &display, format)?;
+                out.flush()
+            });
+            match written {
+                Ok(()) => eprint!("\rWrote {path} at {samples} samples per pixel\n"),
+                Err(e) => eprint!("\rCannot write snapshot {path}: {e}\n"),
+            }
+        });
+    }
 
-        // Render
-        let mut out = std::io::stdout();
-        println!("P3\n{} {}\n255", self.image_width, self.image_height);
+    /// Render in passes, calling `on_pass` with the image so far and the samples per
+    /// pixel reached after each one, and return the final image. Progressive renders
+    /// double the sample count with every pass until `samples_per_pixel` or the time
+    /// limit is reached; otherwise there is a single pass. The first pass always
+    /// completes, so even a time limit that has already run out gives a whole image.
+    pub fn render_progressive(
+        &mut self,
+        world: &impl Hittable,
+        mut on_pass: impl FnMut(&Image, usize),
+    ) -> Image {
+        let deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        let eye_offsets = self.eye_offsets();
 
-        for j in 0..self.image_height {
-            eprint!("\rScanlines remaining: {}", self.image_height - j);
-            for i in 0..self.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _sample in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color += self.ray_color(&r, self.max_depth, world);
+        self.eye_offset = eye_offsets[0];
+        self.initialize();
+        if !std::mem::take(&mut self.resumed) {
+            let rows = 0..self.image_height as usize;
+            self.films = eye_offsets
+                .iter()
+                .map(|_| self.new_film(rows.clone()))
+                .collect();
+        }
+        let (has_checkpoint, interval) = (self.checkpoint.is_some(), self.checkpoint_interval);
+        let checkpoint_due = || has_checkpoint.then(|| Instant::now() + interval);
+        let mut next_checkpoint = checkpoint_due();
+
+        let mut samples = 0;
+        while samples < self.samples_per_pixel {
+            let target = if self.progressive {
+                (2 * samples).clamp(1, self.samples_per_pixel)
+            } else {
+                self.samples_per_pixel
+            };
+            let pass_deadline = deadline.filter(|_| samples > 0);
+            let mut cut_short = false;
+            for (eye, &eye_offset) in eye_offsets.iter().enumerate() {
+                if eye_offsets.len() > 1 {
+                    self.eye_offset = eye_offset;
+                    self.initialize();
                 }
+                // Break the pass up wherever a checkpoint falls due.
+                let mut row = 0;
+                while row < self.image_height {
+                    let stop_at = pass_deadline.into_iter().chain(next_checkpoint).min();
+                    let mut film = std::mem::take(&mut self.films[eye]);
+                    row =
+                        self.render_pass(world, &mut film, target, row..self.image_height, stop_at);
+                    self.films[eye] = film;
+                    if pass_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        cut_short |= row < self.image_height;
+                        break;
+                    }
+                    if row < self.image_height {
+                        self.save_checkpoint();
+                        next_checkpoint = checkpoint_due();
+                    }
+                }
+            }
+            // A pass cut short leaves some pixels at the previous pass's count.
+            if !cut_short {
+                samples = target;
+            }
 
-                write_color(&mut out, self.pixel_samples_scale * pixel_color);
+            on_pass(&self.image(), samples);
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
             }
         }
-        eprint!("\rDone                         \n")
+        self.save_checkpoint();
+        eprint!("\rDone                         \n");
+        self.image()
     }
 
-    fn initialize(&mut self) {
-        self.image_height = (self.image_width as f64 / self.aspect_ratio) as u64;
-        self.image_height = if self.image_height < 1 {
+    /// Write the films to the checkpoint file, if there is one. The sample pattern
+    /// is a function of the seed, pixel and sample index, so the films and the
+    /// settings that select the pattern are all a resumed render needs. A failed
+    /// write is reported and rendering carries on.
+    fn save_checkpoint(&self) {
+        let Some(path) = &self.checkpoint else {
+            return;
+        };
+        // Write beside the old checkpoint and swap, so being killed mid-write
+        // leaves the previous one intact.
+        let partial = format!("{path}.partial");
+        let written = File::create(&partial)
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                out.write_all(CHECKPOINT_MAGIC)?;
+                for value in self.checkpoint_settings() {
+                    write_u64(&mut out, value)?;
+                }
+                for film in &self.films {
+                    film.write_to(&mut out)?;
+                }
+                out.into_inner()?.sync_all()
+            })
+            .and_then(|()| fs::rename(&partial, path));
+        if let Err(e) = written {
+            eprint!("\rCannot write checkpoint {path}: {e}\n");
+        }
+    }
+
+    /// Load the films saved by an earlier render with the same settings, so the
+    /// next render continues from them. The result matches an uninterrupted render.
+    pub fn resume(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        io::Read::read_exact(&mut input, &mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        for expected in self.checkpoint_settings() {
+            if read_u64(&mut input)? != expected {
+                return Err(invalid("checkpoint was made with different settings"));
+            }
+        }
+        let (width, height) = (self.image_width as usize, self.image_height() as usize);
+        let eyes = if self.stereo == StereoMode::Mono {
             1
         } else {
-            self.image_height
+            2
+        };
+        let mut films = Vec::new();
+        for _ in 0..eyes {
+            films.push(Film::read_from(
+                &mut input,
+                width,
+                0..height,
+                self.film_light_groups(),
+            )?);
+        }
+
+        self.films = films;
+        self.resumed = true;
+        Ok(())
+    }
+
+    /// The settings a checkpoint is only valid for.
+    fn checkpoint_settings(&self) -> [u64; 9] {
+        [
+            self.image_width,
+            self.image_height(),
+            self.stereo as u64,
+            self.samples_per_pixel as u64,
+            self.sampler as u64,
+            self.seed,
+            self.aovs as u64,
+            self.background as u64,
+            self.description,
+        ]
+    }
+
+    /// Start a render made of separately rendered tiles of `rows_per_tile` rows,
+    /// returning the tiles still to be rendered. They can be rendered in any order,
+    /// and by other processes with the same settings, with `render_tile`.
+    pub fn tiles(&mut self, rows_per_tile: u64) -> Vec<Tile> {
+        self.initialize();
+        let rows = 0..self.image_height as usize;
+        let eyes = self.eye_offsets().len();
+        self.films = (0..eyes).map(|_| self.new_film(rows.clone())).collect();
+
+        let mut tiles = Vec::new();
+        for eye in 0..eyes {
+            for start in (0..self.image_height).step_by(rows_per_tile.max(1) as usize) {
+                let end = (start + rows_per_tile).min(self.image_height);
+                tiles.push(Tile {
+                    eye,
+                    rows: start..end,
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Render every sample of one tile.
+    pub fn render_tile(&mut self, world: &impl Hittable, tile: &Tile) -> Film {
+        self.eye_offset = self.eye_offsets()[tile.eye];
+        self.initialize();
+        let rows = tile.rows.start as usize..tile.rows.end as usize;
+        let mut film = self.new_film(rows);
+        self.render_pass(
+            world,
+            &mut film,
+            self.samples_per_pixel,
+            tile.rows.clone(),
+            None,
+        );
+        film
+    }
+
+    /// Copy a rendered tile into the image.
+    pub fn merge_tile(&mut self, tile: &Tile, film: &Film) {
+        self.films[tile.eye].paste(film);
+    }
+
+    fn new_film(&self, rows: Range<usize>) -> Film {
+        let film = Film::band(self.image_width as usize, rows);
+        match self.film_light_groups() {
+            Some(groups) => film.with_aovs(groups),
+            None => film,
+        }
+    }
+
+    /// The number of light groups in each film pixel's AOV sums, or `None` when
+    /// AOVs are not being recorded.
+    pub fn film_light_groups(&self) -> Option<usize> {
+        self.aovs.then_some(self.light_groups.len())
+    }
+
+    /// The image accumulated so far, denoised if asked to be and with the post
+    /// effects applied.
+    pub fn image(&self) -> Image {
+        self.compose(|film| {
+            let image = if self.denoise {
+                let guides = Guides {
+                    albedo: surface_image(film, |a| a.albedo.clone()),
+                    normal: surface_image(film, |a| a.normal.into()),
+                    depth: depth_image(film),
+                    error: film.standard_errors(),
+                };
+                denoise(&film.image(), &guides)
+            } else {
+                film.image()
+            };
+            self.post.apply(&image, self.seed)
+        })
+    }
+
+    /// Coverage alpha, if the background is not visible.
+    pub fn alpha(&self) -> Option<Image> {
+        (self.background != Background::Visible).then(|| self.compose(Film::coverage))
+    }
+
+    /// The fraction of `samples_per_pixel` each pixel of the last render received,
+    /// as a grey level.
+    pub fn sample_counts(&self) -> Image {
+        self.compose(|film| film.sample_counts(self.samples_per_pixel))
+    }
+
+    /// The image with any alpha, followed by any AOVs, as layers of an EXR file. The
+    /// colour is premultiplied by the alpha, as EXR expects. Depth is the
+    /// distance to the first hit, infinite where nothing was hit, and normals and
+    /// positions are in world space. Each light group has the part of the image
+    /// lit by it.
+    pub fn layers(&self) -> Vec<Layer> {
+        let mut layers = vec![Layer::new("", &["R", "G", "B"], self.image())];
+        if let Some(alpha) = self.alpha() {
+            layers.push(Layer::new("", &["A"], alpha));
+        }
+        if !self.aovs {
+            return layers;
+        }
+
+        let grey = |v: f64| Color::new(v, v, v);
+        let surface = |f: fn(&AovSums) -> Color| self.compose(|film| surface_image(film, f));
+        layers.push(Layer::new("", &["Z"], self.compose(depth_image)));
+        layers.push(Layer::new(
+            "N",
+            &["X", "Y", "Z"],
+            surface(|a| a.normal.into()),
+        ));
+        layers.push(Layer::new(
+            "P",
+            &["X", "Y", "Z"],
+            surface(|a| a.position.into()),
+        ));
+        layers.push(Layer::new(
+            "albedo",
+            &["R", "G", "B"],
+            surface(|a| a.albedo.clone()),
+        ));
+        let object_ids = self.compose(|film| film.aov_image(|a, _| grey(a.object_id as f64)));
+        layers.push(Layer::new("", &["objectId"], object_ids));
+        let material_ids = self.compose(|film| film.aov_image(|a, _| grey(a.material_id as f64)));
+        layers.push(Layer::new("", &["materialId"], material_ids));
+        for (group, name) in self.light_groups.iter().enumerate() {
+            let image = self.compose(|film| {
+                film.aov_image(|aovs, pixel| {
+                    if pixel.weight_sum == 0.0 {
+                        Color::default()
+                    } else {
+                        (1.0 / pixel.weight_sum) * aovs.lights[group].clone()
+                    }
+                })
+            });
+            layers.push(Layer::new(
+                &format!("light.{name}"),
+                &["R", "G", "B"],
+                image,
+            ));
+        }
+        layers
+    }
+
+    /// Lay out an image of each eye's film according to the stereo mode.
+    fn compose(&self, f: impl Fn(&Film) -> Image) -> Image {
+        match self.films.as_slice() {
+            [left, right] => self.stereo_pair(&f(left), &f(right)),
+            [mono] => f(mono),
+            _ => Image::new(0, 0),
+        }
+    }
+
+    /// The offset of each eye along u: a single centred eye unless rendering stereo.
+    fn eye_offsets(&self) -> Vec<f64> {
+        if self.stereo == StereoMode::Mono {
+            vec![0.0]
+        } else {
+            let half_ipd = self.interpupillary_distance / 2.0;
+            vec![-half_ipd, half_ipd]
+        }
+    }
+
+    /// Place the left and right eye images according to the stereo mode.
+    fn stereo_pair(&self, left: &Image, right: &Image) -> Image {
+        let (width, height) = (left.width(), left.height());
+        let (mut image, right_corner) = if self.stereo == StereoMode::SideBySide {
+            (Image::new(2 * width, height), (width, 0))
+        } else {
+            (Image::new(width, 2 * height), (0, height))
         };
+        image.paste(left, 0, 0);
+        image.paste(right, right_corner.0, right_corner.1);
+        image
+    }
+
+    /// Add samples to every pixel in `rows` of the current eye's film until it has
+    /// `target`, or has converged. Returns the row to continue from, which is before
+    /// the end of `rows` if `stop_at` came first.
+    fn render_pass(
+        &self,
+        world: &impl Hittable,
+        film: &mut Film,
+        target: usize,
+        rows: Range<u64>,
+        stop_at: Option<Instant>,
+    ) -> u64 {
+        let mut sampler = self.sampler.create(self.samples_per_pixel, self.seed);
+        let mut sample_aovs = self.aovs.then(|| SampleAovs::new(self.light_groups.len()));
 
-        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
+        for j in rows.clone() {
+            if stop_at.is_some_and(|stop_at| Instant::now() >= stop_at) {
+                return j;
+            }
+            eprint!("\rScanlines remaining: {}  ", self.image_height - j);
+            for i in 0..self.image_width {
+                let pixel = film.pixel_mut(i as usize, j as usize);
+                while !pixel.converged && pixel.samples < target {
+                    sampler.start_pixel_sample(i, j, pixel.samples);
+                    pixel.samples += 1;
+                    let (offset, weight) = self.filter_sampler.sample(sampler.get_2d());
+                    if let Some(sample_aovs) = &mut sample_aovs {
+                        sample_aovs.clear();
+                    }
+                    let (mut color, mut hit) = (Color::new(0.0, 0.0, 0.0), false);
+                    if let Some(r) = self.get_ray(i, j, offset, &mut *sampler) {
+                        (color, hit) =
+                            self.ray_color(&r, world, &mut *sampler, sample_aovs.as_mut());
+                    }
+                    pixel.stats.add(color.luminance(), weight);
+                    pixel.weight_sum += weight;
+                    if hit {
+                        pixel.coverage_sum += weight;
+                    }
+                    pixel.color_sum += weight * color;
+                    if let (Some(sums), Some(sample)) = (&mut pixel.aovs, &sample_aovs) {
+                        sums.add(sample, weight);
+                    }
 
-        self.center = self.lookfrom;
+                    pixel.converged = self.adaptive_threshold > 0.0
+                        && pixel.samples >= self.min_samples_per_pixel
+                        && pixel.stats.relative_error() <= self.adaptive_threshold;
+                }
+            }
+        }
+        rows.end
+    }
+
+    fn image_height(&self) -> u64 {
+        ((self.image_width as f64 / self.aspect_ratio) as u64).max(1)
+    }
+
+    fn initialize(&mut self) {
+        self.image_height = self.image_height();
+
+        self.filter_sampler = self.filter.sampler();
 
         let theta = self.vfov.to_radians();
         let h = (theta / 2.).tan();
@@ -93,6 +615,13 @@ impl Camera {
         self.u = Vec3::unit_vector(Vec3::cross(self.vup, self.w));
         self.v = Vec3::cross(self.w, self.u);
 
+        // Stereo eyes sit either side of lookfrom. Panoramas offset each ray instead.
+        self.center = if self.projection == Projection::Equirectangular {
+            self.lookfrom
+        } else {
+            self.lookfrom + self.eye_offset * self.u
+        };
+
         // Calculate the vectors across the horizontal and down the vertical viewport edges.
         let viewport_u = viewport_width * self.u;
         let viewport_v = viewport_height * -self.v;
@@ -101,56 +630,495 @@ impl Camera {
         self.pixel_delta_u = viewport_u / self.image_width as f64;
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left =
-            self.center - (self.focus_dist * self.w) - viewport_u / 2. - viewport_v / 2.;
+        // Calculate the location of the upper left pixel. For a stereo eye the viewport
+        // is shifted towards the other eye so both views coincide at convergence_dist.
+        let convergence_shift = self.eye_offset * self.focus_dist / self.convergence_dist;
+        let viewport_upper_left = self.center
+            - (self.focus_dist * self.w)
+            - viewport_u / 2.
+            - viewport_v / 2.
+            - convergence_shift * self.u
+            + self.shift_x * viewport_u
+            - self.shift_y * viewport_v;
 
         self.pixel100_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
+        // The plane of sharp focus, tilted and swung away from the viewport plane.
+        self.focus_plane_point = self.center - self.focus_dist * self.w;
+        self.focus_plane_normal = Vec3::unit_vector(
+            self.w + self.tilt.to_radians().tan() * self.v + self.swing.to_radians().tan() * self.u,
+        );
+
+        // Lens prescriptions are in millimetres, the scene in metres.
+        if let Some(lens) = &mut self.lens {
+            lens.focus(1000.0 * self.focus_dist, self.film_diagonal);
+        }
+
         // Calculate the camera defocus disk basis vectors.
         let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
         self.defocus_disk_u = defocus_radius * self.u;
         self.defocus_disk_v = defocus_radius * self.v;
     }
 
-    pub fn ray_color(&self, r: &Ray, depth: usize, world: &impl Hittable) -> Color {
-        if depth <= 0 {
-            return Color::new(0.0, 0., 0.);
+    /// Follow a path from the camera through at most `max_depth` surfaces and return
+    /// the light it carries back, and whether the camera ray hit anything. With
+    /// `aovs`, also record the first hit and the light from each light group.
+    pub fn ray_color(
+        &self,
+        r: &Ray,
+        world: &impl Hittable,
+        sampler: &mut dyn Sampler,
+        mut aovs: Option<&mut SampleAovs>,
+    ) -> (Color, bool) {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        let mut hit = false;
+        for bounce in 0..self.max_depth {
+            let Some(rec) = world.hit(&ray, Interval::new(0.001, f64::INFINITY)) else {
+                let hidden = match self.background {
+                    Background::Visible => false,
+                    Background::Transparent => true,
+                    Background::InvisibleEnvironment => bounce == 0,
+                };
+                if hidden {
+                    break;
+                }
+                let unit_direction = Vec3::unit_vector(ray.direction);
+                let a = 0.5 * (unit_direction.y + 1.0);
+                let sky = (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0);
+                let light = throughput * (self.sky_intensity * sky);
+                add_light(&mut color, aovs.as_deref_mut(), 0, light);
+                break;
+            };
+
+            hit |= bounce == 0;
+            if bounce == 0
+                && let Some(aovs) = aovs.as_deref_mut()
+            {
+                aovs.first_hit = Some(FirstHit {
+                    depth: rec.t * ray.direction.length(),
+                    normal: rec.normal,
+                    position: rec.p,
+                    albedo: rec.mat.albedo(&rec),
+                    object_id: rec.object_id,
+                    material_id: rec.material_id,
+                });
+            }
+            let emitted = throughput.clone() * rec.mat.emitted(&rec);
+            add_light(
+                &mut color,
+                aovs.as_deref_mut(),
+                rec.mat.light_group(),
+                emitted,
+            );
+
+            let Some((attenuation, scattered)) = rec.mat.scatter(&ray, &rec, sampler) else {
+                break;
+            };
+            throughput = throughput * attenuation;
+            ray = scattered;
+        }
+        (color, hit)
+    }
+
+    /// Construct a camera ray originating from the defocus disk and directed at the
+    /// point `offset` pixels away from the centre of pixel i, j.
+    /// Returns `None` when a lens system blocks the sample.
+    pub fn get_ray(&self, i: u64, j: u64, offset: Vec3, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let x = i as f64 + offset.x;
+        let y = j as f64 + offset.y;
+
+        if let Some(lens) = &self.lens {
+            return self.get_lens_ray(lens, x, y, sampler);
         }
-        if let Some(rec) = world.hit(r, Interval::new(0.001, f64::INFINITY)) {
-            if let Some((attentuation, scattered)) = rec.mat.scatter(r, &rec) {
-                return attentuation * self.ray_color(&scattered, depth - 1, world);
-            } else {
-                return Color::new(0.0, 0.0, 0.0);
+
+        let pixel_sample = self.pixel100_loc + x * self.pixel_delta_u + y * self.pixel_delta_v;
+
+        // Where the ray leaves the lens centre and a point on it that is in focus.
+        let (origin, focus_point) = match self.projection {
+            Projection::Perspective => (self.center, pixel_sample),
+            Projection::Orthographic => (pixel_sample + self.focus_dist * self.w, pixel_sample),
+            Projection::Fisheye => {
+                let direction = self.panoramic_direction(x, y);
+                (self.center, self.center + self.focus_dist * direction)
+            }
+            Projection::Equirectangular => {
+                // Omni-directional stereo: the eye circles the centre, always offset
+                // sideways from the view direction, shrinking to nothing at the poles.
+                let direction = self.panoramic_direction(x, y);
+                let eye = self.center + self.eye_offset * Vec3::cross(direction, self.v);
+                let converged = self.center + self.convergence_dist * direction - eye;
+                (eye, eye + self.focus_dist * Vec3::unit_vector(converged))
             }
+        };
+
+        let focus_point = self.refocus(origin, focus_point);
+
+        let ray_origin = if self.defocus_angle <= 0.0 {
+            origin
+        } else {
+            origin + self.defocus_disk_sample(x, y, sampler)
+        };
+        let ray_direction = focus_point - ray_origin;
+        Some(Ray::new(ray_origin, ray_direction))
+    }
+
+    /// Trace a ray from the continuous pixel position x, y on the film out through
+    /// the lens system.
+    fn get_lens_ray(
+        &self,
+        lens: &LensSystem,
+        x: f64,
+        y: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        let width = self.image_width as f64;
+        let height = self.image_height as f64;
+        let mm_per_pixel = self.film_diagonal / width.hypot(height);
+
+        // The lens forms an inverted image, so the film point mirrors the pixel.
+        let film_x = -(x + 0.5 - width / 2.) * mm_per_pixel;
+        let film_y = -(height / 2. - y - 0.5) * mm_per_pixel;
+        let r = lens.sample_ray(film_x, film_y, sampler)?;
+
+        // Lens space looks down -z in millimetres; the camera looks down -w in metres.
+        let to_world = |v: Vec3| v.x * self.u + v.y * self.v + v.z * self.w;
+        Some(Ray::new(
+            self.center + 0.001 * to_world(r.origin),
+            to_world(r.direction),
+        ))
+    }
+
+    /// Move a point seen from `origin` along its line of sight onto the tilted focal
+    /// plane, so the defocus disk keeps that plane sharp.
+    fn refocus(&self, origin: Point3, focus_point: Point3) -> Point3 {
+        if self.tilt == 0.0 && self.swing == 0.0 {
+            return focus_point;
+        }
+        let direction = focus_point - origin;
+        let denom = Vec3::dot(direction, self.focus_plane_normal);
+        let t = Vec3::dot(self.focus_plane_point - origin, self.focus_plane_normal) / denom;
+        if t > 0.0 && t.is_finite() {
+            origin + t * direction
+        } else {
+            // Looking parallel to or away from the plane: nothing along the ray is sharp.
+            focus_point
         }
+    }
+
+    /// Unit view direction through the continuous pixel position x, y for the
+    /// non-planar projections.
+    fn panoramic_direction(&self, x: f64, y: f64) -> Vec3 {
+        let width = self.image_width as f64;
+        let height = self.image_height as f64;
+        // Pixel centres sit at integer coordinates.
+        let (x, y) = (x + 0.5, y + 0.5);
+
+        // Components of the direction along u, v and the view axis -w.
+        let (along_u, along_v, forward) = match self.projection {
+            Projection::Fisheye => {
+                // Image-plane offset from the centre, 1 at the top and bottom edges.
+                let px = (x - width / 2.) / (height / 2.);
+                let py = (height / 2. - y) / (height / 2.);
+                let theta = (px * px + py * py).sqrt() * self.vfov.to_radians() / 2.;
+                let phi = py.atan2(px);
+                (
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                )
+            }
+            _ => {
+                let longitude = 2. * PI * (x / width - 0.5);
+                let latitude = PI * (0.5 - y / height);
+                (
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                )
+            }
+        };
 
-        let unit_direction = Vec3::unit_vector(r.direction);
-        let a = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        along_u * self.u + along_v * self.v - forward * self.w
     }
 
-    /// Construct a camera ray originating from the defocus disk
-    /// and directed at a randomly sampled point around the pixel location i, j.
-    pub fn get_ray(&self, i: u64, j: u64) -> Ray {
-        let offset = self.sample_square();
-        let pixel_sample = self.pixel100_loc
-            + (i as f64 + offset.x) * self.pixel_delta_u
-            + (j as f64 + offset.y) * self.pixel_delta_v;
-        let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+    /// Returns a random offset from the lens centre within the defocus disk, shaped
+    /// by the aperture as seen from the continuous pixel position x, y.
+    fn defocus_disk_sample(&self, x: f64, y: f64, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut p = self.aperture.sample(sampler);
+
+        if self.cats_eye > 0.0 {
+            // Off axis the lens barrel clips the aperture with a circle shifted towards
+            // the image edge, turning round highlights into cat's eyes.
+            let half_diagonal = 0.5 * (self.image_width as f64).hypot(self.image_height as f64);
+            let clip_center = Vec3::new(
+                self.cats_eye * (x + 0.5 - 0.5 * self.image_width as f64) / half_diagonal,
+                self.cats_eye * (0.5 * self.image_height as f64 - y - 0.5) / half_diagonal,
+                0.0,
+            );
+            // Masks can overlap the circle by very little, so give up after a while
+            // and let the light through the middle of the clipped opening.
+            const MAX_TRIES: usize = 64;
+            let clipped = |p: Vec3| (p - clip_center).length_squared() > 1.0;
+            for _ in 0..MAX_TRIES {
+                if !clipped(p) {
+                    break;
+                }
+                p = self.aperture.sample(sampler);
+            }
+            if clipped(p) {
+                p = clip_center;
+            }
+        }
+
+        (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    }
+}
+
+/// The mean over the samples that hit a surface of a quantity recorded about it.
+fn surface_image(film: &Film, f: fn(&AovSums) -> Color) -> Image {
+    film.aov_image(|aovs, _| {
+        if aovs.hit_weight == 0.0 {
+            Color::default()
+        } else {
+            (1.0 / aovs.hit_weight) * f(aovs)
+        }
+    })
+}
+
+/// The mean distance to the first hit, infinite where nothing was hit.
+fn depth_image(film: &Film) -> Image {
+    film.aov_image(|aovs, _| {
+        let depth = if aovs.hit_weight == 0.0 {
+            f64::INFINITY
         } else {
-            self.defocus_disk_sample()
+            aovs.depth / aovs.hit_weight
         };
-        let ray_direction = pixel_sample - ray_origin;
-        Ray::new(ray_origin, ray_direction)
+        Color::new(depth, depth, depth)
+    })
+}
+
+/// Add light from a light group to a path's colour and to the group's AOV.
+fn add_light(color: &mut Color, aovs: Option<&mut SampleAovs>, group: usize, light: Color) {
+    if let Some(sum) = aovs.and_then(|aovs| aovs.lights.get_mut(group)) {
+        *sum += light.clone();
+    }
+    *color += light;
+}
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTCHKPT2";
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        camera::{Background, Camera, Projection},
+        color::{Color, unpremultiply},
+        hittable_list::HittableList,
+        image::Image,
+        material::{DiffuseLight, Lambertian, Material},
+        quad::Quad,
+        sampler::SamplerKind,
+        vec3::{Point3, Vec3},
+    };
+
+    /// A camera one pixel high looking down -z, each pixel seeing a square 2 units
+    /// across at z = -1.
+    fn camera(width: u64, background: Background) -> Camera {
+        let mut camera = Camera::new();
+        camera.aspect_ratio = width as f64;
+        camera.image_width = width;
+        camera.samples_per_pixel = 1024;
+        camera.sampler = SamplerKind::Sobol;
+        camera.background = background;
+        camera
+    }
+
+    /// A wall at z = -1 filling everything left of x = 0.
+    fn left_wall<'a>(world: &mut HittableList<'a>, mat: impl Material + 'a) {
+        let corner = Point3::new(-10.0, -10.0, -1.0);
+        world.add(Quad::new(
+            corner,
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            mat,
+        ));
+    }
+
+    #[test]
+    fn test_coverage_alpha_of_a_half_covered_pixel() {
+        let emit = Color::new(0.8, 0.4, 0.2);
+        let mut world = HittableList::new();
+        left_wall(&mut world, DiffuseLight::new(emit.clone(), 0));
+        let mut camera = camera(1, Background::Transparent);
+        camera.render(&world);
+
+        let alpha = Vec3::from(camera.alpha().unwrap().pixel(0, 0).clone()).x;
+        assert!((alpha - 0.5).abs() < 0.02, "{alpha}");
+
+        // The image is premultiplied; dividing by the alpha gives the wall's colour.
+        let straight = Vec3::from(unpremultiply(camera.image().pixel(0, 0).clone(), alpha));
+        assert!(
+            (straight - Vec3::from(emit)).length() < 1e-9,
+            "{straight:?}"
+        );
     }
 
-    fn sample_square(&self) -> Vec3 {
-        Vec3::new(random_f64() - 0.5, random_f64() - 0.5, 0.)
+    #[test]
+    fn test_invisible_environment_is_transparent() {
+        let mut world = HittableList::new();
+        left_wall(&mut world, Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut camera = camera(2, Background::InvisibleEnvironment);
+        camera.render(&world);
+        let (image, alpha) = (camera.image(), camera.alpha().unwrap());
+        let at = |image: &Image, x| Vec3::from(image.pixel(x, 0).clone());
+
+        // The wall is lit by the sky it cannot see...
+        assert_eq!(1.0, at(&alpha, 0).x);
+        assert!(at(&image, 0).x > 0.1);
+        // ...and the sky itself leaves nothing behind.
+        assert_eq!(0.0, at(&alpha, 1).x);
+        assert_eq!(0.0, at(&image, 1).length());
     }
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
-        self.center + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    #[test]
+    fn test_projections_map_pixels_to_view_directions() {
+        let (forward, up) = (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+
+        // A 180° fisheye sees straight up at the middle of its top edge.
+        let mut fisheye = Camera::new();
+        fisheye.vfov = 180.0;
+        fisheye.projection = Projection::Fisheye;
+        fisheye.initialize();
+        assert!(close(forward, fisheye.panoramic_direction(49.5, 49.5)));
+        assert!(close(up, fisheye.panoramic_direction(49.5, -0.5)));
+
+        // An equirectangular panorama goes once around from behind to behind.
+        let mut panorama = Camera::new();
+        (panorama.aspect_ratio, panorama.image_width) = (2.0, 200);
+        panorama.projection = Projection::Equirectangular;
+        panorama.initialize();
+        assert!(close(forward, panorama.panoramic_direction(99.5, 49.5)));
+        assert!(close(-forward, panorama.panoramic_direction(-0.5, 49.5)));
+        let left = Vec3::new(-1.0, 0.0, 0.0);
+        assert!(close(left, panorama.panoramic_direction(49.5, 49.5)));
+        assert!(close(up, panorama.panoramic_direction(99.5, -0.5)));
+
+        // Orthographic rays are parallel and start from the viewport's own points.
+        let mut orthographic = Camera::new();
+        orthographic.projection = Projection::Orthographic;
+        orthographic.initialize();
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        let mut ray = |i, j, offset| {
+            orthographic
+                .get_ray(i, j, offset, sampler.as_mut())
+                .unwrap()
+        };
+        for (i, j) in [(0, 0), (99, 20)] {
+            let direction = ray(i, j, Vec3::zero()).direction;
+            assert!(close(forward, Vec3::unit_vector(direction)));
+        }
+        let f = orthographic.focus_dist;
+        let corner = ray(0, 0, Vec3::new(-0.5, -0.5, 0.0));
+        assert!(close(Vec3::new(-f, f, 0.0), corner.origin));
+    }
+
+    #[test]
+    fn test_tilt_and_shift() {
+        let mut camera = Camera::new();
+        camera.focus_dist = 1.0;
+        camera.tilt = 45.0;
+        camera.initialize();
+
+        // Points across the view are refocused, along their line of sight, onto the
+        // plane through (0, 0, -1) tilted 45° about the horizontal: z = -1 - y.
+        let centre = camera.center;
+        for y in [0.5, 0.1, -0.5] {
+            let seen = Point3::new(0.3, y, -1.0);
+            let p = camera.refocus(centre, seen);
+            assert!((p.z + 1.0 + p.y).abs() < 1e-9);
+            assert!(close(
+                Vec3::unit_vector(seen - centre),
+                Vec3::unit_vector(p - centre)
+            ));
+        }
+        let top = camera.refocus(centre, Point3::new(0.0, 0.5, -1.0));
+        let bottom = camera.refocus(centre, Point3::new(0.0, -0.5, -1.0));
+        assert!(top.z < -1.0 && bottom.z > -1.0);
+
+        // Shifting up by a quarter of the view moves the middle of the viewport up by
+        // half a unit at z = -1 without turning the camera.
+        camera.tilt = 0.0;
+        camera.shift_y = 0.25;
+        camera.initialize();
+        let middle = camera.pixel100_loc + 49.5 * (camera.pixel_delta_u + camera.pixel_delta_v);
+        assert!(close(Point3::new(0.0, 0.5, -1.0), middle));
+        assert!(close(Vec3::new(0.0, 0.0, 1.0), camera.w));
+    }
+
+    #[test]
+    fn test_adaptive_sampling_stops_where_the_estimate_has_converged() {
+        // A flat black wall on the left, noisy sky-lit diffuse wall on the right.
+        let mut world = HittableList::new();
+        left_wall(&mut world, Lambertian::new(Color::new(0.0, 0.0, 0.0)));
+        let corner = Point3::new(0.0, -10.0, -1.0);
+        let (u, v) = (Vec3::new(10.0, 0.0, 0.0), Vec3::new(0.0, 20.0, 0.0));
+        world.add(Quad::new(
+            corner,
+            u,
+            v,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+
+        let mut camera = camera(2, Background::Visible);
+        camera.sampler = SamplerKind::Independent;
+        (camera.samples_per_pixel, camera.min_samples_per_pixel) = (256, 16);
+        camera.adaptive_threshold = 0.005;
+        camera.render_progressive(&world, |_, _| {});
+        let counts = camera.sample_counts();
+        let fraction = |x| Vec3::from(counts.pixel(x, 0).clone()).x;
+        // Identical samples still leave the error at 1 / sqrt(n (n - 1)), so the
+        // flat pixel goes on past the minimum until that reaches the threshold.
+        assert_eq!(201.0 / 256.0, fraction(0));
+        assert_eq!(1.0, fraction(1));
+
+        camera.adaptive_threshold = 0.0;
+        camera.render_progressive(&world, |_, _| {});
+        let counts = camera.sample_counts();
+        assert_eq!(1.0, Vec3::from(counts.pixel(0, 0).clone()).x);
+    }
+
+    #[test]
+    fn test_progressive_passes_double_up_to_the_full_render() {
+        let mut world = HittableList::new();
+        left_wall(&mut world, Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut camera = camera(2, Background::Visible);
+        camera.samples_per_pixel = 20;
+        let single = camera.render_progressive(&world, |_, _| {});
+
+        camera.progressive = true;
+        let mut passes = Vec::new();
+        let progressive = camera.render_progressive(&world, |_, samples| passes.push(samples));
+        assert_eq!(vec![1, 2, 4, 8, 16, 20], passes);
+        for x in 0..2 {
+            let difference = Vec3::from(progressive.pixel(x, 0).clone())
+                - Vec3::from(single.pixel(x, 0).clone());
+            assert!(difference.length() < 1e-12);
+        }
+
+        // Already out of time: the first pass still covers the whole image.
+        camera.time_limit = Some(Duration::ZERO);
+        passes.clear();
+        let timed = camera.render_progressive(&world, |_, samples| passes.push(samples));
+        assert_eq!(vec![1], passes);
+        for x in 0..2 {
+            assert!(Vec3::from(timed.pixel(x, 0).clone()).length() > 0.1);
+        }
     }
 }
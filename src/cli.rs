@@ -0,0 +1,650 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    aperture::{Aperture, ApertureMask},
+    camera::{Background, Camera, Projection, StereoMode},
+    colorspace::ColorSpace,
+    dither::Dither,
+    filter::Filter,
+    image::Image,
+    lens_system::LensSystem,
+    lut::{Interpolation, Lut},
+    netpbm::NetpbmFormat,
+    sampler::{SamplerKind, hash},
+    tonemap::ToneMapper,
+};
+
+pub const USAGE: &str = "\
+Usage: raytrace [SCENE] [OPTIONS] > image.ppm
+       raytrace [SCENE] [OPTIONS] --output image.ppm
+
+Scenes: final (default), three-spheres, layered-materials, mapped-spheres, cutout-fence,
+        light-groups
+
+Options:
+    --filter NAME       pixel filter: box, tent, gaussian, mitchell, lanczos or
+                        blackman-harris
+    --sampler NAME      independent, stratified, halton, sobol or blue-noise
+    --spp N             samples per pixel, the maximum when sampling adaptively
+    --adaptive ERROR    stop sampling a pixel once the standard error of its
+                        luminance falls below this fraction of it, e.g. 0.02
+    --min-spp N         samples per pixel before adaptive sampling may stop
+                        (default 16)
+    --progressive       render passes of doubling sample counts up to --spp
+    --snapshot FILE     rewrite FILE with the image after every pass; implies
+                        --progressive
+    --time-limit SECONDS
+                        stop sampling after this long and output the image so far;
+                        implies --progressive, and the first pass always completes
+    --checkpoint FILE   save the render state to FILE periodically and at the end
+    --checkpoint-interval SECONDS
+                        time between checkpoints (default 60)
+    --resume            continue from --checkpoint if it exists; the options
+                        other than where to write, the checkpoint and the time
+                        limit must be given as for the interrupted render;
+                        not with --coordinator
+    --coordinator ADDRESS
+                        listen on ADDRESS, e.g. 0.0.0.0:7878, and hand tiles of
+                        the render to workers instead of rendering locally
+    --worker ADDRESS    render tiles for the coordinator at ADDRESS
+    --tile-rows N       rows per distributed tile (default 16)
+    --worker-timeout SECONDS
+                        give a tile to another worker after this long
+                        (default 600)
+    --chromatic-aberration AMOUNT
+                        magnify red and blue by +/- AMOUNT, e.g. 0.005
+    --vignetting STRENGTH
+                        darken towards the corners, e.g. 1
+    --bloom INTENSITY   spread INTENSITY of the light above the bloom threshold
+                        into a glow, e.g. 0.1
+    --bloom-threshold LUMINANCE
+                        (default 1)
+    --bloom-radius PIXELS
+                        width of the glow (default 32)
+    --glare INTENSITY   spread INTENSITY of the light above the glare threshold
+                        into star streaks, e.g. 0.05
+    --glare-threshold LUMINANCE
+                        (default 2)
+    --glare-blades N    aperture blades making the streaks (default 6)
+    --glare-length PIXELS
+                        (default 48)
+    --glare-rotation DEGREES
+                        turn the streaks anticlockwise
+    --grain AMOUNT      film grain relative to the light, e.g. 0.05
+    --exposure EV       brighten the output by EV stops, or darken if negative
+    --tone-map NAME     clamp (default), reinhard, extended-reinhard, hable, aces
+                        or agx
+    --white-point VALUE exposed scene value shown as white
+    --working-space NAME
+                        colour space of the scene's colours: srgb (default),
+                        acescg, display-p3 or rec2020
+    --output-space NAME colour space of the PPM and PNG output, from the same list
+    --lut FILE          grade the output with a 1D or 3D .cube LUT after tone mapping
+    --lut-interpolation NAME
+                        trilinear (default) or tetrahedral
+    --dither NAME       noise added when quantising to 8 bits against banding:
+                        none (default), triangular, bayer or blue-noise
+    -o, --output FILE   write the image to FILE instead of standard output
+    --format NAME       Netpbm variant of the image and snapshots: p3 (default,
+                        plain text), p6 (binary), p6-16 (16-bit binary), pgm
+                        (greyscale) or pam (RGB with alpha)
+    --png FILE          also write the image as a PNG tagged with its colour space
+    --denoise           filter out noise guided by the albedo, normals and depth
+    --aovs FILE         also write the image with depth, normal, position, albedo,
+                        object and material ID and light group layers to FILE as
+                        an OpenEXR file
+    --sample-map FILE   write the fraction of --spp each pixel received as a PPM
+    --seed N            seed for the sample pattern (default 0)
+    --scene-seed N      seed for the random spheres of the final scene (default 0)
+    --projection NAME   perspective, orthographic, fisheye or equirectangular
+    --normal-map FILE   PPM normal map for the mapped-spheres scene
+    --background NAME   what camera rays that miss the scene see: visible
+                        (default), transparent (no sky at all) or
+                        invisible-environment (the sky still lights the scene);
+                        the latter two write coverage alpha to PNG, PAM and EXR
+    --stereo MODE       mono, side-by-side or over-under
+    --ipd DISTANCE      interpupillary distance for stereo, in scene units
+    --convergence DIST  distance at which the stereo views line up
+    --blades N          polygonal aperture with N diaphragm blades
+    --blade-rotation DEGREES
+                        rotation of the polygonal aperture
+    --aperture-mask FILE
+                        PPM image giving the aperture shape
+    --cats-eye AMOUNT   off-axis aperture clipping, 0 (none) to 1 (strong)
+    --shift-x FRACTION  horizontal lens shift, as a fraction of the image width
+    --shift-y FRACTION  vertical lens shift, as a fraction of the image height
+    --tilt DEGREES      focal plane tilt about the horizontal axis
+    --swing DEGREES     focal plane swing about the vertical axis
+    --lens FILE         trace through a lens prescription, e.g. lenses/dgauss.50mm.dat
+    --film-diagonal MM  film size used with --lens (default 35)";
+
+/// Command line settings that override what a scene sets up.
+pub struct Args {
+    pub raw: Vec<String>, // As given, for describing the render to workers
+    pub scene: String,
+    pub normal_map: Option<String>,
+    pub filter: Option<Filter>,
+    pub sampler: Option<SamplerKind>,
+    pub samples_per_pixel: Option<usize>,
+    pub min_samples_per_pixel: Option<usize>,
+    pub adaptive_threshold: Option<f64>,
+    pub sample_map: Option<String>,
+    pub aov_file: Option<String>,
+    pub denoise: bool,
+    pub chromatic_aberration: Option<f64>,
+    pub vignetting: Option<f64>,
+    pub bloom: Option<f64>,
+    pub bloom_threshold: Option<f64>,
+    pub bloom_radius: Option<f64>,
+    pub glare: Option<f64>,
+    pub glare_threshold: Option<f64>,
+    pub glare_blades: Option<usize>,
+    pub glare_length: Option<f64>,
+    pub glare_rotation: Option<f64>,
+    pub grain: Option<f64>,
+    pub exposure: Option<f64>,
+    pub white_point: Option<f64>,
+    pub tone_mapper: Option<ToneMapper>,
+    pub working_space: Option<ColorSpace>,
+    pub output_space: Option<ColorSpace>,
+    pub output: Option<String>,
+    pub format: Option<NetpbmFormat>,
+    pub png: Option<String>,
+    pub lut: Option<String>,
+    pub lut_interpolation: Option<Interpolation>,
+    pub dither: Option<Dither>,
+    pub progressive: bool,
+    pub snapshot: Option<String>,
+    pub time_limit: Option<f64>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Option<f64>,
+    pub resume: bool,
+    pub coordinator: Option<String>,
+    pub worker: Option<String>,
+    pub tile_rows: u64,
+    pub worker_timeout: Duration,
+    pub seed: Option<u64>,
+    pub scene_seed: u64,
+    pub projection: Option<Projection>,
+    pub background: Option<Background>,
+    pub stereo: Option<StereoMode>,
+    pub interpupillary_distance: Option<f64>,
+    pub convergence_dist: Option<f64>,
+    pub blades: Option<usize>,
+    pub blade_rotation: f64,
+    pub aperture_mask: Option<String>,
+    pub cats_eye: Option<f64>,
+    pub shift_x: Option<f64>,
+    pub shift_y: Option<f64>,
+    pub tilt: Option<f64>,
+    pub swing: Option<f64>,
+    pub lens: Option<String>,
+    pub film_diagonal: Option<f64>,
+}
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let raw: Vec<String> = args.collect();
+        let mut args = raw.clone().into_iter();
+        let mut parsed = Args {
+            raw,
+            scene: String::from("final"),
+            normal_map: None,
+            filter: None,
+            sampler: None,
+            samples_per_pixel: None,
+            min_samples_per_pixel: None,
+            adaptive_threshold: None,
+            sample_map: None,
+            aov_file: None,
+            denoise: false,
+            chromatic_aberration: None,
+            vignetting: None,
+            bloom: None,
+            bloom_threshold: None,
+            bloom_radius: None,
+            glare: None,
+            glare_threshold: None,
+            glare_blades: None,
+            glare_length: None,
+            glare_rotation: None,
+            grain: None,
+            exposure: None,
+            white_point: None,
+            tone_mapper: None,
+            working_space: None,
+            output_space: None,
+            output: None,
+            format: None,
+            png: None,
+            lut: None,
+            lut_interpolation: None,
+            dither: None,
+            progressive: false,
+            snapshot: None,
+            time_limit: None,
+            checkpoint: None,
+            checkpoint_interval: None,
+            resume: false,
+            coordinator: None,
+            worker: None,
+            tile_rows: 16,
+            worker_timeout: Duration::from_secs(600),
+            seed: None,
+            scene_seed: 0,
+            projection: None,
+            background: None,
+            stereo: None,
+            interpupillary_distance: None,
+            convergence_dist: None,
+            blades: None,
+            blade_rotation: 0.0,
+            aperture_mask: None,
+            cats_eye: None,
+            shift_x: None,
+            shift_y: None,
+            tilt: None,
+            swing: None,
+            lens: None,
+            film_diagonal: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+            match arg.as_str() {
+                "--filter" => parsed.filter = Some(value()?.parse()?),
+                "--sampler" => parsed.sampler = Some(value()?.parse()?),
+                "--spp" => parsed.samples_per_pixel = Some(count(&value()?)?),
+                "--min-spp" => parsed.min_samples_per_pixel = Some(count(&value()?)?),
+                "--adaptive" => parsed.adaptive_threshold = Some(number(&value()?)?),
+                "--sample-map" => parsed.sample_map = Some(value()?),
+                "--aovs" => parsed.aov_file = Some(value()?),
+                "--denoise" => parsed.denoise = true,
+                "--chromatic-aberration" => parsed.chromatic_aberration = Some(number(&value()?)?),
+                "--vignetting" => parsed.vignetting = Some(number(&value()?)?),
+                "--bloom" => parsed.bloom = Some(number(&value()?)?),
+                "--bloom-threshold" => parsed.bloom_threshold = Some(number(&value()?)?),
+                "--bloom-radius" => {
+                    let radius = number(&value()?)?;
+                    if radius <= 0.0 {
+                        return Err(String::from("--bloom-radius must be positive"));
+                    }
+                    parsed.bloom_radius = Some(radius);
+                }
+                "--glare" => parsed.glare = Some(number(&value()?)?),
+                "--glare-threshold" => parsed.glare_threshold = Some(number(&value()?)?),
+                "--glare-blades" => parsed.glare_blades = Some(count(&value()?)?),
+                "--glare-length" => parsed.glare_length = Some(number(&value()?)?),
+                "--glare-rotation" => parsed.glare_rotation = Some(number(&value()?)?),
+                "--grain" => parsed.grain = Some(number(&value()?)?),
+                "--exposure" => parsed.exposure = Some(number(&value()?)?),
+                "--white-point" => {
+                    let white_point = number(&value()?)?;
+                    if white_point <= 0.0 {
+                        return Err(String::from("--white-point must be positive"));
+                    }
+                    parsed.white_point = Some(white_point);
+                }
+                "--tone-map" => parsed.tone_mapper = Some(value()?.parse()?),
+                "--working-space" => parsed.working_space = Some(value()?.parse()?),
+                "--output-space" => parsed.output_space = Some(value()?.parse()?),
+                "--output" | "-o" => parsed.output = Some(value()?),
+                "--format" => parsed.format = Some(value()?.parse()?),
+                "--png" => parsed.png = Some(value()?),
+                "--lut" => parsed.lut = Some(value()?),
+                "--lut-interpolation" => parsed.lut_interpolation = Some(value()?.parse()?),
+                "--dither" => parsed.dither = Some(value()?.parse()?),
+                "--progressive" => parsed.progressive = true,
+                "--snapshot" => parsed.snapshot = Some(value()?),
+                "--time-limit" => parsed.time_limit = Some(number(&value()?)?),
+                "--checkpoint" => parsed.checkpoint = Some(value()?),
+                "--checkpoint-interval" => parsed.checkpoint_interval = Some(number(&value()?)?),
+                "--resume" => parsed.resume = true,
+                "--coordinator" => parsed.coordinator = Some(value()?),
+                "--worker" => parsed.worker = Some(value()?),
+                "--tile-rows" => parsed.tile_rows = count(&value()?)? as u64,
+                "--worker-timeout" => parsed.worker_timeout = duration(number(&value()?)?)?,
+                "--seed" => parsed.seed = Some(seed(&value()?)?),
+                "--scene-seed" => parsed.scene_seed = seed(&value()?)?,
+                "--projection" => parsed.projection = Some(value()?.parse()?),
+                "--normal-map" => parsed.normal_map = Some(value()?),
+                "--background" => parsed.background = Some(value()?.parse()?),
+                "--stereo" => parsed.stereo = Some(value()?.parse()?),
+                "--ipd" => parsed.interpupillary_distance = Some(number(&value()?)?),
+                "--convergence" => {
+                    let dist = number(&value()?)?;
+                    if dist <= 0.0 {
+                        return Err(String::from("--convergence must be positive"));
+                    }
+                    parsed.convergence_dist = Some(dist);
+                }
+                "--blades" => parsed.blades = Some(count(&value()?)?),
+                "--blade-rotation" => parsed.blade_rotation = number(&value()?)?,
+                "--aperture-mask" => parsed.aperture_mask = Some(value()?),
+                "--cats-eye" => {
+                    let amount = number(&value()?)?;
+                    if !(0.0..=1.0).contains(&amount) {
+                        return Err(String::from("--cats-eye must be between 0 and 1"));
+                    }
+                    parsed.cats_eye = Some(amount);
+                }
+                "--shift-x" => parsed.shift_x = Some(number(&value()?)?),
+                "--shift-y" => parsed.shift_y = Some(number(&value()?)?),
+                "--tilt" => parsed.tilt = Some(number(&value()?)?),
+                "--swing" => parsed.swing = Some(number(&value()?)?),
+                "--lens" => parsed.lens = Some(value()?),
+                "--film-diagonal" => parsed.film_diagonal = Some(number(&value()?)?),
+                "-h" | "--help" => return Err(String::new()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option: {arg}")),
+                _ => parsed.scene = arg,
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Apply the command line overrides to a scene's camera.
+    pub fn configure(&self, camera: &mut Camera) -> Result<(), String> {
+        if let Some(filter) = self.filter {
+            camera.filter = filter;
+        }
+        if let Some(sampler) = self.sampler {
+            camera.sampler = sampler;
+        }
+        if let Some(spp) = self.samples_per_pixel {
+            camera.samples_per_pixel = spp;
+        }
+        if let Some(min_spp) = self.min_samples_per_pixel {
+            camera.min_samples_per_pixel = min_spp;
+        }
+        if let Some(threshold) = self.adaptive_threshold {
+            camera.adaptive_threshold = threshold;
+        }
+        if self.progressive || self.snapshot.is_some() || self.time_limit.is_some() {
+            camera.progressive = true;
+        }
+        if self.snapshot.is_some() {
+            camera.snapshot = self.snapshot.clone();
+        }
+        if let Some(seconds) = self.time_limit {
+            camera.time_limit = Some(duration(seconds)?);
+        }
+        if self.checkpoint.is_some() {
+            camera.checkpoint = self.checkpoint.clone();
+        }
+        if let Some(seconds) = self.checkpoint_interval {
+            camera.checkpoint_interval = duration(seconds)?;
+        }
+        if self.aov_file.is_some() {
+            camera.aovs = true;
+        }
+        if self.denoise {
+            camera.denoise = true;
+            camera.aovs = true;
+        }
+        if let Some(chromatic_aberration) = self.chromatic_aberration {
+            camera.post.chromatic_aberration = chromatic_aberration;
+        }
+        if let Some(vignetting) = self.vignetting {
+            camera.post.vignetting = vignetting;
+        }
+        if let Some(intensity) = self.bloom {
+            camera.post.bloom.intensity = intensity;
+        }
+        if let Some(threshold) = self.bloom_threshold {
+            camera.post.bloom.threshold = threshold;
+        }
+        if let Some(radius) = self.bloom_radius {
+            camera.post.bloom.radius = radius;
+        }
+        if let Some(intensity) = self.glare {
+            camera.post.glare.intensity = intensity;
+        }
+        if let Some(threshold) = self.glare_threshold {
+            camera.post.glare.threshold = threshold;
+        }
+        if let Some(blades) = self.glare_blades {
+            camera.post.glare.blades = blades;
+        }
+        if let Some(length) = self.glare_length {
+            camera.post.glare.length = length;
+        }
+        if let Some(rotation) = self.glare_rotation {
+            camera.post.glare.rotation = rotation;
+        }
+        if let Some(grain) = self.grain {
+            camera.post.grain = grain;
+        }
+        if let Some(exposure) = self.exposure {
+            camera.display.exposure = exposure;
+        }
+        if let Some(white_point) = self.white_point {
+            camera.display.white_point = Some(white_point);
+        }
+        if let Some(tone_mapper) = self.tone_mapper {
+            camera.display.tone_mapper = tone_mapper;
+        }
+        if let Some(space) = self.working_space {
+            camera.display.working_space = space;
+        }
+        if let Some(space) = self.output_space {
+            camera.display.output_space = space;
+        }
+        if let Some(path) = &self.lut {
+            let mut lut = Lut::load(path).map_err(|e| format!("Cannot load LUT {path}: {e}"))?;
+            lut.interpolation = self.lut_interpolation.unwrap_or_default();
+            camera.display.lut = Some(lut);
+        }
+        if let Some(format) = self.format {
+            camera.format = format;
+        }
+        if let Some(dither) = self.dither {
+            camera.display.dither = dither;
+        }
+        if let Some(seed) = self.seed {
+            camera.seed = seed;
+        }
+        if let Some(projection) = self.projection {
+            camera.projection = projection;
+        }
+        if let Some(background) = self.background {
+            camera.background = background;
+        }
+        if let Some(stereo) = self.stereo {
+            camera.stereo = stereo;
+        }
+        if let Some(ipd) = self.interpupillary_distance {
+            camera.interpupillary_distance = ipd;
+        }
+        if let Some(convergence_dist) = self.convergence_dist {
+            camera.convergence_dist = convergence_dist;
+        }
+        if let Some(blades) = self.blades {
+            camera.aperture = Aperture::Polygon {
+                blades,
+                rotation: self.blade_rotation,
+            };
+        }
+        if let Some(path) = &self.aperture_mask {
+            let mask = Image::load_ppm(path)
+                .map_err(|e| format!("Cannot load aperture mask {path}: {e}"))?;
+            let mask = ApertureMask::new(&mask)
+                .map_err(|e| format!("Cannot use aperture mask {path}: {e}"))?;
+            camera.aperture = Aperture::Mask(mask);
+        }
+        if let Some(cats_eye) = self.cats_eye {
+            camera.cats_eye = cats_eye;
+        }
+        if let Some(shift_x) = self.shift_x {
+            camera.shift_x = shift_x;
+        }
+        if let Some(shift_y) = self.shift_y {
+            camera.shift_y = shift_y;
+        }
+        if let Some(tilt) = self.tilt {
+            camera.tilt = tilt;
+        }
+        if let Some(swing) = self.swing {
+            camera.swing = swing;
+        }
+        if let Some(path) = &self.lens {
+            let lens =
+                LensSystem::load(path).map_err(|e| format!("Cannot load lens {path}: {e}"))?;
+            camera.lens = Some(lens);
+        }
+        if let Some(film_diagonal) = self.film_diagonal {
+            camera.film_diagonal = film_diagonal;
+        }
+        // Panoramas and lens systems have no flat viewport to shift, and a lens
+        // system has a film plane of its own instead of a tilted focal plane.
+        let shifted = camera.shift_x != 0.0 || camera.shift_y != 0.0;
+        let panoramic = matches!(
+            camera.projection,
+            Projection::Fisheye | Projection::Equirectangular
+        );
+        if shifted && (panoramic || camera.lens.is_some()) {
+            return Err(String::from(
+                "--shift-x and --shift-y need a perspective or orthographic projection and no --lens",
+            ));
+        }
+        if (camera.tilt != 0.0 || camera.swing != 0.0) && camera.lens.is_some() {
+            return Err(String::from(
+                "--tilt and --swing cannot be used with --lens",
+            ));
+        }
+        camera.description = render_description(&self.raw);
+        // Last, so the checkpoint is checked against the final settings.
+        if self.resume {
+            let path = (self.checkpoint.as_ref()).ok_or("--resume needs --checkpoint")?;
+            if self.coordinator.is_some() {
+                return Err(String::from("--resume cannot be used with --coordinator"));
+            }
+            if Path::new(path).exists() {
+                camera
+                    .resume(path)
+                    .map_err(|e| format!("Cannot resume from {path}: {e}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Options whose value is a file the render reads. Distributed workers are sent
+/// the contents, as they may not see the coordinator's files.
+pub const INPUT_FILE_OPTIONS: [&str; 4] = ["--lut", "--lens", "--aperture-mask", "--normal-map"];
+
+/// Options that only say where the results go, how the render is checkpointed or
+/// how long it may take, which may change when it is resumed. All but `--resume`
+/// take a value.
+const NOT_DESCRIBING_RENDER: [&str; 11] = [
+    "--output",
+    "-o",
+    "--format",
+    "--png",
+    "--aovs",
+    "--sample-map",
+    "--snapshot",
+    "--checkpoint",
+    "--checkpoint-interval",
+    "--resume",
+    "--time-limit",
+];
+
+/// A hash of the arguments that decide the image being rendered.
+fn render_description(raw: &[String]) -> u64 {
+    let mut words = Vec::new();
+    let mut args = raw.iter();
+    while let Some(arg) = args.next() {
+        if NOT_DESCRIBING_RENDER.contains(&arg.as_str()) {
+            if arg != "--resume" {
+                args.next();
+            }
+            continue;
+        }
+        // A terminator after each argument keeps "ab c" apart from "a bc".
+        words.extend(arg.bytes().map(u64::from));
+        words.push(u64::MAX);
+    }
+    hash(&words)
+}
+
+fn number(s: &str) -> Result<f64, String> {
+    s.parse().map_err(|_| format!("Not a number: {s}"))
+}
+
+fn duration(seconds: f64) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("Not a duration: {seconds}"))
+}
+
+fn count(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("Not a count: {s}"))
+}
+
+fn seed(s: &str) -> Result<u64, String> {
+    s.parse().map_err(|_| format!("Not a seed: {s}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        camera::Camera,
+        cli::{Args, render_description},
+    };
+
+    #[test]
+    fn test_convergence_must_be_positive() {
+        let parse = |args: &str| Args::parse(args.split_whitespace().map(String::from));
+        assert!(parse("final --convergence 0").is_err());
+        assert!(parse("final --convergence -2").is_err());
+        assert_eq!(
+            Some(2.5),
+            parse("final --convergence 2.5").unwrap().convergence_dist
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_rejected_while_parsing() {
+        let parse = |args: &str| Args::parse(args.split_whitespace().map(String::from));
+        assert!(parse("final --bloom-radius 0").is_err());
+        assert!(parse("final --white-point -1").is_err());
+        assert!(parse("final --cats-eye 1.5").is_err());
+    }
+
+    #[test]
+    fn test_render_description_ignores_only_outputs_and_checkpoints() {
+        let describe = |args: &str| {
+            let raw: Vec<String> = args.split_whitespace().map(String::from).collect();
+            render_description(&raw)
+        };
+        let base = describe("final --spp 64 --filter box");
+        assert_eq!(
+            base,
+            describe("final --spp 64 -o a.ppm --filter box --checkpoint c --resume --time-limit 9")
+        );
+        assert_ne!(base, describe("final --spp 64 --filter tent"));
+        assert_ne!(
+            base,
+            describe("final --spp 64 --filter box --sampler sobol")
+        );
+    }
+
+    #[test]
+    fn test_distributed_renders_cannot_resume() {
+        let args = "final --coordinator 0.0.0.0:7878 --checkpoint render.chk --resume";
+        let args = Args::parse(args.split_whitespace().map(String::from)).unwrap();
+        assert!(args.configure(&mut Camera::new()).is_err());
+    }
+
+    #[test]
+    fn test_shift_and_tilt_need_a_flat_viewport() {
+        let configure = |args: &str| {
+            let args = Args::parse(args.split_whitespace().map(String::from)).unwrap();
+            args.configure(&mut Camera::new())
+        };
+        assert!(configure("final --shift-x 0.1 --tilt 5").is_ok());
+        assert!(configure("final --projection orthographic --shift-y 0.1").is_ok());
+        assert!(configure("final --projection fisheye --shift-x 0.1").is_err());
+        assert!(configure("final --projection equirectangular --shift-y 0.1").is_err());
+    }
+}
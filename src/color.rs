@@ -1,11 +1,15 @@
 use std::{
-    io::Write,
+    io::{self, Write},
     ops::{Add, AddAssign, Mul},
 };
 
-use crate::{interval::Interval, vec3::Vec3};
+use crate::{
+    colorspace::{ColorSpace, transform},
+    dither::Dither,
+    vec3::Vec3,
+};
 
-#[derive(Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Color {
     r: f64,
     g: f64,
@@ -16,6 +20,19 @@ impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Color {
         Color { r, g, b }
     }
+
+    /// Relative luminance using the Rec. 709 weights.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// The same colour with its components given in another colour space.
+    pub fn convert(&self, from: ColorSpace, to: ColorSpace) -> Color {
+        if from == to {
+            return self.clone();
+        }
+        transform(&from.conversion_to(to), self.clone().into()).into()
+    }
 }
 
 impl AddAssign for Color {
@@ -32,6 +49,12 @@ impl From<Vec3> for Color {
     }
 }
 
+impl From<Color> for Vec3 {
+    fn from(c: Color) -> Vec3 {
+        Vec3::new(c.r, c.g, c.b)
+    }
+}
+
 impl Mul<Color> for f64 {
     type Output = Color;
     fn mul(self, other: Color) -> Color {
@@ -53,22 +76,39 @@ impl Add for Color {
     }
 }
 
-pub fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0. {
-        return linear_component.sqrt();
+/// The sRGB transfer function, from linear light to the encoded value.
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear.max(0.0)
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The colour of the covered part of a pixel, from its colour premultiplied by
+/// its coverage `alpha`.
+pub fn unpremultiply(color: Color, alpha: f64) -> Color {
+    if alpha > 0.0 {
+        (1.0 / alpha) * color
+    } else {
+        color
     }
-    0.
 }
 
-pub fn write_color(out: &mut impl Write, pixel_color: Color) {
-    let r = linear_to_gamma(pixel_color.r);
-    let g = linear_to_gamma(pixel_color.g);
-    let b = linear_to_gamma(pixel_color.b);
+/// Write a display-encoded colour as a line of a plain PPM, dithered for its
+/// position (x, y) in the image.
+pub fn write_color(
+    out: &mut impl Write,
+    pixel_color: Color,
+    dither: Dither,
+    x: usize,
+    y: usize,
+) -> io::Result<()> {
+    let (r, g, b) = (pixel_color.r, pixel_color.g, pixel_color.b);
 
-    let intensity = Interval::new(0.0, 0.999);
-    let rbyte = (256.0 * intensity.clamp(r)) as usize;
-    let gbyte = (256.0 * intensity.clamp(g)) as usize;
-    let bbyte = (256.0 * intensity.clamp(b)) as usize;
+    let rbyte = dither.quantize(r, x, y, 0);
+    let gbyte = dither.quantize(g, x, y, 1);
+    let bbyte = dither.quantize(b, x, y, 2);
 
-    writeln!(out, "{rbyte} {gbyte} {bbyte}").unwrap();
+    writeln!(out, "{rbyte} {gbyte} {bbyte}")
 }
@@ -0,0 +1,235 @@
+use std::str::FromStr;
+
+use crate::{color::linear_to_srgb, vec3::Vec3};
+
+/// A 3×3 matrix applied to column vectors of RGB or XYZ.
+pub type Matrix = [[f64; 3]; 3];
+
+pub fn transform(m: &Matrix, v: Vec3) -> Vec3 {
+    let row = |r: &[f64; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+    Vec3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f64 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = cofactor(j, i) / det;
+        }
+    }
+    inverse
+}
+
+fn diagonal(v: Vec3) -> Matrix {
+    [[v.x, 0.0, 0.0], [0.0, v.y, 0.0], [0.0, 0.0, v.z]]
+}
+
+/// CIE xy coordinates of a colour space's primaries and white point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chromaticities {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white: (f64, f64),
+}
+
+const D65: (f64, f64) = (0.3127, 0.3290);
+pub const D50: (f64, f64) = (0.3457, 0.3585);
+
+/// The XYZ of a chromaticity with a luminance of 1.
+fn xy_to_xyz((x, y): (f64, f64)) -> Vec3 {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+/// RGB colour spaces that scenes can be rendered in and images written in.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    /// sRGB, which shares its primaries and white with Rec. 709.
+    #[default]
+    Srgb,
+    /// The ACES working space AP1, linear with a white near D60.
+    AcesCg,
+    /// DCI-P3 primaries with a D65 white and the sRGB transfer function.
+    DisplayP3,
+    /// Rec. 2020 primaries with the Rec. 2020 transfer function.
+    Rec2020,
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ColorSpace, String> {
+        match s {
+            "srgb" | "rec709" => Ok(ColorSpace::Srgb),
+            "acescg" => Ok(ColorSpace::AcesCg),
+            "display-p3" => Ok(ColorSpace::DisplayP3),
+            "rec2020" => Ok(ColorSpace::Rec2020),
+            _ => Err(format!("Unknown colour space: {s}")),
+        }
+    }
+}
+
+impl ColorSpace {
+    pub fn chromaticities(&self) -> Chromaticities {
+        match self {
+            ColorSpace::Srgb => Chromaticities {
+                red: (0.64, 0.33),
+                green: (0.30, 0.60),
+                blue: (0.15, 0.06),
+                white: D65,
+            },
+            ColorSpace::AcesCg => Chromaticities {
+                red: (0.713, 0.293),
+                green: (0.165, 0.830),
+                blue: (0.128, 0.044),
+                white: (0.32168, 0.33767),
+            },
+            ColorSpace::DisplayP3 => Chromaticities {
+                red: (0.680, 0.320),
+                green: (0.265, 0.690),
+                blue: (0.150, 0.060),
+                white: D65,
+            },
+            ColorSpace::Rec2020 => Chromaticities {
+                red: (0.708, 0.292),
+                green: (0.170, 0.797),
+                blue: (0.131, 0.046),
+                white: D65,
+            },
+        }
+    }
+
+    /// The matrix from linear RGB in this space to XYZ, scaled so that white has a
+    /// luminance of 1.
+    pub fn rgb_to_xyz(&self) -> Matrix {
+        let c = self.chromaticities();
+        let [r, g, b] = [c.red, c.green, c.blue].map(xy_to_xyz);
+        let primaries = [[r.x, g.x, b.x], [r.y, g.y, b.y], [r.z, g.z, b.z]];
+        let scale = transform(&invert(&primaries), xy_to_xyz(c.white));
+        multiply(&primaries, &diagonal(scale))
+    }
+
+    /// The matrix from linear RGB in this space to linear RGB in `other`, adapting
+    /// this space's white to the other's.
+    pub fn conversion_to(&self, other: ColorSpace) -> Matrix {
+        let adapt = bradford(self.chromaticities().white, other.chromaticities().white);
+        multiply(
+            &invert(&other.rgb_to_xyz()),
+            &multiply(&adapt, &self.rgb_to_xyz()),
+        )
+    }
+
+    /// Apply the space's transfer function to a linear value in [0, 1].
+    pub fn encode(&self, linear: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => linear_to_srgb(linear),
+            ColorSpace::AcesCg => linear,
+            ColorSpace::Rec2020 => {
+                const ALPHA: f64 = 1.09929682680944;
+                const BETA: f64 = 0.018053968510807;
+                if linear < BETA {
+                    4.5 * linear
+                } else {
+                    ALPHA * linear.powf(0.45) - (ALPHA - 1.0)
+                }
+            }
+        }
+    }
+
+    /// The inverse of `encode`.
+    pub fn decode(&self, encoded: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => {
+                if encoded <= 0.04045 {
+                    encoded / 12.92
+                } else {
+                    ((encoded + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            ColorSpace::AcesCg => encoded,
+            ColorSpace::Rec2020 => {
+                const ALPHA: f64 = 1.09929682680944;
+                if encoded < 4.5 * 0.018053968510807 {
+                    encoded / 4.5
+                } else {
+                    ((encoded + ALPHA - 1.0) / ALPHA).powf(1.0 / 0.45)
+                }
+            }
+        }
+    }
+}
+
+/// The Bradford chromatic adaptation from one white to another, in XYZ.
+pub fn bradford(from: (f64, f64), to: (f64, f64)) -> Matrix {
+    const CONE: Matrix = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    let source = transform(&CONE, xy_to_xyz(from));
+    let destination = transform(&CONE, xy_to_xyz(to));
+    let gain = Vec3::new(
+        destination.x / source.x,
+        destination.y / source.y,
+        destination.z / source.z,
+    );
+    multiply(&invert(&CONE), &multiply(&diagonal(gain), &CONE))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        colorspace::{ColorSpace, transform},
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_srgb_matrix_and_round_trips() {
+        let m = ColorSpace::Srgb.rgb_to_xyz();
+        assert!((m[0][0] - 0.4124).abs() < 1e-4);
+        assert!((m[1][1] - 0.7152).abs() < 1e-4);
+        assert!((m[2][2] - 0.9505).abs() < 1e-4);
+
+        let spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::AcesCg,
+            ColorSpace::DisplayP3,
+            ColorSpace::Rec2020,
+        ];
+        let color = Vec3::new(0.2, 0.5, 0.8);
+        for from in spaces {
+            for to in spaces {
+                let there = transform(&from.conversion_to(to), color);
+                let back = transform(&to.conversion_to(from), there);
+                assert!((back - color).length() < 1e-9, "{from:?} to {to:?}");
+            }
+            // White stays white, whatever the space's white point.
+            let white = transform(
+                &from.conversion_to(ColorSpace::Srgb),
+                Vec3::new(1.0, 1.0, 1.0),
+            );
+            assert!(
+                (white - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-3,
+                "{from:?}"
+            );
+            for x in [0.0, 0.001, 0.01, 0.5, 1.0] {
+                assert!((from.decode(from.encode(x)) - x).abs() < 1e-12, "{from:?}");
+            }
+        }
+    }
+}
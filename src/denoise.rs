@@ -0,0 +1,210 @@
+use crate::{color::Color, image::Image, vec3::Vec3};
+
+/// The AOVs that steer the denoiser, each the size of the noisy image.
+pub struct Guides {
+    pub albedo: Image,
+    pub normal: Image,
+    pub depth: Image, // Infinite where nothing was hit
+    pub error: Image, // Standard error of each pixel's luminance, as a grey level
+}
+
+const ITERATIONS: usize = 5;
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const LUMINANCE_SIGMA: f64 = 4.0; // Luminance difference tolerated, in standard errors
+const NORMAL_POWER: f64 = 32.0;
+const DEPTH_SIGMA: f64 = 0.05; // Depth difference tolerated, relative to the nearer depth
+
+/// Remove sampling noise with an edge-avoiding À-trous wavelet filter: a 5×5
+/// B-spline kernel applied with doubling gaps between taps, where each tap is
+/// weighted down by how much its normal, depth and luminance differ from the
+/// pixel's. The luminance tolerance scales with the estimated noise, which is
+/// filtered alongside the colour and blurred before use, since a few samples that
+/// happen to agree can badly underestimate it. The colour is divided by the albedo
+/// first and multiplied back afterwards, so textures stay sharp.
+pub fn denoise(image: &Image, guides: &Guides) -> Image {
+    let (width, height) = (image.width(), image.height());
+    let at = |image: &Image, p: usize| Vec3::from(image.pixel(p % width, p / width).clone());
+
+    let mut albedo = Vec::with_capacity(width * height);
+    let mut irradiance = Vec::with_capacity(width * height);
+    let mut variance = Vec::with_capacity(width * height);
+    for p in 0..width * height {
+        // Unlit and black surfaces have nothing to demodulate.
+        let a = at(&guides.albedo, p);
+        let a = Vec3::new(demodulator(a.x), demodulator(a.y), demodulator(a.z));
+        let c = at(image, p);
+        let error = at(&guides.error, p).x / luminance(a);
+        albedo.push(a);
+        irradiance.push(Vec3::new(c.x / a.x, c.y / a.y, c.z / a.z));
+        variance.push(error * error);
+    }
+    // Pixels straddling an edge average to shorter normals; only the direction counts.
+    let normals: Vec<Vec3> = (0..width * height)
+        .map(|p| at(&guides.normal, p))
+        .map(|n| {
+            if n.near_zero() {
+                n
+            } else {
+                Vec3::unit_vector(n)
+            }
+        })
+        .collect();
+    let depths: Vec<f64> = (0..width * height)
+        .map(|p| at(&guides.depth, p).x)
+        .collect();
+
+    for iteration in 0..ITERATIONS {
+        let gap = 1 << iteration;
+        let local_variance = blur(&variance, width, height);
+        let mut filtered = Vec::with_capacity(width * height);
+        let mut filtered_variance = Vec::with_capacity(width * height);
+        for p in 0..width * height {
+            let (x, y) = (p % width, p / width);
+            let sigma = LUMINANCE_SIGMA * local_variance[p].sqrt() + 1e-6;
+            let center = luminance(irradiance[p]);
+
+            let mut weight_sum = 0.0;
+            let mut sum = Vec3::zero();
+            let mut variance_sum = 0.0;
+            for (j, ky) in KERNEL.iter().enumerate() {
+                let Some(qy) = (y + j * gap).checked_sub(2 * gap).filter(|&qy| qy < height) else {
+                    continue;
+                };
+                for (i, kx) in KERNEL.iter().enumerate() {
+                    let Some(qx) = (x + i * gap).checked_sub(2 * gap).filter(|&qx| qx < width)
+                    else {
+                        continue;
+                    };
+                    let q = qy * width + qx;
+                    let weight = kx
+                        * ky
+                        * (-(luminance(irradiance[q]) - center).abs() / sigma).exp()
+                        * normal_weight(normals[p], normals[q])
+                        * depth_weight(depths[p], depths[q]);
+                    if weight > 0.0 {
+                        weight_sum += weight;
+                        sum += weight * irradiance[q];
+                        variance_sum += weight * weight * variance[q];
+                    }
+                }
+            }
+            filtered.push(sum / weight_sum);
+            filtered_variance.push(variance_sum / (weight_sum * weight_sum));
+        }
+        irradiance = filtered;
+        variance = filtered_variance;
+    }
+
+    let mut denoised = Image::new(width, height);
+    for p in 0..width * height {
+        denoised.set_pixel(p % width, p / width, (irradiance[p] * albedo[p]).into());
+    }
+    denoised
+}
+
+/// A 3×3 Gaussian blur of a buffer of values.
+fn blur(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const WEIGHTS: [f64; 3] = [0.25, 0.5, 0.25];
+    let mut blurred = Vec::with_capacity(values.len());
+    for p in 0..values.len() {
+        let (x, y) = (p % width, p / width);
+        let (mut sum, mut weight_sum) = (0.0, 0.0);
+        for (j, wy) in WEIGHTS.iter().enumerate() {
+            for (i, wx) in WEIGHTS.iter().enumerate() {
+                let (qx, qy) = ((x + i).wrapping_sub(1), (y + j).wrapping_sub(1));
+                if qx < width && qy < height {
+                    sum += wx * wy * values[qy * width + qx];
+                    weight_sum += wx * wy;
+                }
+            }
+        }
+        blurred.push(sum / weight_sum);
+    }
+    blurred
+}
+
+fn demodulator(albedo: f64) -> f64 {
+    if albedo < 0.01 { 1.0 } else { albedo }
+}
+
+fn luminance(v: Vec3) -> f64 {
+    Color::from(v).luminance()
+}
+
+/// Background pixels have no normal and only match each other.
+fn normal_weight(n: Vec3, m: Vec3) -> f64 {
+    if n == m {
+        1.0
+    } else {
+        Vec3::dot(n, m).max(0.0).powf(NORMAL_POWER)
+    }
+}
+
+fn depth_weight(z: f64, w: f64) -> f64 {
+    if z == w {
+        1.0
+    } else {
+        (-(z - w).abs() / (DEPTH_SIGMA * z.min(w))).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        denoise::{Guides, denoise},
+        image::Image,
+    };
+
+    fn filled(width: usize, height: usize, f: impl Fn(usize, usize) -> Color) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, f(x, y));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_denoise_smooths_noise_but_keeps_edges() {
+        // Two walls meeting down the middle, bright on the left and dark on the
+        // right, with uniform noise of standard deviation 0.058.
+        let (width, height) = (32, 16);
+        let grey = |v: f64| Color::new(v, v, v);
+        let expected = |x: usize| if x < 16 { 0.8 } else { 0.2 };
+        let mut rng = fastrand::Rng::with_seed(1);
+        let noise: Vec<f64> = (0..width * height).map(|_| rng.f64() * 0.2 - 0.1).collect();
+        let noisy = filled(width, height, |x, y| {
+            grey(expected(x) + noise[y * width + x])
+        });
+        let guides = Guides {
+            albedo: filled(width, height, |_, _| grey(1.0)),
+            normal: filled(width, height, |x, _| {
+                if x < 16 {
+                    Color::new(1.0, 0.0, 0.0)
+                } else {
+                    Color::new(0.0, 0.0, 1.0)
+                }
+            }),
+            depth: filled(width, height, |_, _| grey(2.0)),
+            error: filled(width, height, |_, _| grey(0.058)),
+        };
+
+        let rms_error = |image: &Image| {
+            let mut sum = 0.0;
+            for y in 0..height {
+                for x in 0..width {
+                    sum += (image.pixel(x, y).luminance() - expected(x)).powi(2);
+                }
+            }
+            (sum / (width * height) as f64).sqrt()
+        };
+        let denoised = denoise(&noisy, &guides);
+        assert!(rms_error(&denoised) < rms_error(&noisy) / 4.0);
+        for y in 0..height {
+            assert!(denoised.pixel(15, y).luminance() > 0.7);
+            assert!(denoised.pixel(16, y).luminance() < 0.3);
+        }
+    }
+}
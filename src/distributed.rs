@@ -0,0 +1,385 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    process,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    camera::{Camera, Tile},
+    cli::INPUT_FILE_OPTIONS,
+    film::{Film, read_u64, write_u64},
+    hittable::Hittable,
+};
+
+// The protocol, with every number a little-endian u64:
+//
+// 1. A worker connects and the coordinator sends the command line describing the
+//    render: the argument count, then each argument as a byte length and UTF-8.
+//    The contents of each input file the command line names follow, in order, as
+//    a byte length and the bytes, so workers need not share the coordinator's
+//    files.
+// 2. The coordinator sends a tile as the eye and the first and end rows, and the
+//    worker replies with the tile's film as written by `Film::write_to`. This
+//    repeats until the coordinator sends NO_MORE_TILES in place of an eye, after
+//    which the worker hangs up.
+
+/// Sent in place of a tile's eye when there is nothing left to render.
+const NO_MORE_TILES: u64 = u64::MAX;
+
+/// The largest input file sent to workers.
+const MAX_INPUT_FILE: u64 = 1 << 28;
+
+/// Tiles waiting for a worker, and how many have not come back yet.
+struct Queue {
+    pending: VecDeque<Tile>,
+    outstanding: usize,
+}
+
+/// Render the camera's image by handing tiles of `rows_per_tile` rows to workers
+/// that connect to `listener`, merging their films into the camera. Every worker
+/// is sent `args`, the command line describing the scene and render settings. A
+/// worker that disconnects, or takes longer than `timeout` over a tile, loses the
+/// tile to the next worker that asks for one. Returns once every worker has been
+/// told there are no more tiles, so none is left waiting when the program exits,
+/// or at once if an input file cannot be read.
+pub fn coordinate(
+    camera: &mut Camera,
+    listener: TcpListener,
+    args: &[String],
+    rows_per_tile: u64,
+    timeout: Duration,
+) -> io::Result<()> {
+    let files = input_file_indices(args)
+        .map(|i| {
+            let path = &args[i];
+            fs::read(path).map_err(|e| Error::new(e.kind(), format!("{path}: {e}")))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let tiles = camera.tiles(rows_per_tile);
+    let total = tiles.len();
+    let (width, light_groups) = (camera.image_width as usize, camera.film_light_groups());
+    let queue = Arc::new(Mutex::new(Queue {
+        pending: VecDeque::from(tiles),
+        outstanding: total,
+    }));
+    let (results, finished) = mpsc::channel();
+
+    let job = Arc::new((args.to_vec(), files));
+    let accept_queue = Arc::clone(&queue);
+    // None once the coordinator is finishing, after which no more servers start.
+    let servers = Arc::new(Mutex::new(Some(Vec::new())));
+    let accept_servers = Arc::clone(&servers);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Hold the lock from spawning to recording the server, so the
+            // coordinator joins every server that starts.
+            let mut servers = accept_servers.lock().unwrap();
+            let Some(servers) = servers.as_mut() else {
+                return;
+            };
+            let queue = Arc::clone(&accept_queue);
+            let (results, job) = (results.clone(), Arc::clone(&job));
+            servers.push(thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                let (args, files) = job.as_ref();
+                let served = serve(
+                    stream,
+                    (args, files),
+                    (width, light_groups),
+                    &queue,
+                    &results,
+                    timeout,
+                );
+                if let Err(e) = served {
+                    eprint!("\rLost worker {peer}: {e}\n");
+                }
+            }));
+        }
+    });
+
+    for remaining in (1..=total).rev() {
+        eprint!("\rTiles remaining: {remaining}  ");
+        let Ok((tile, film)) = finished.recv() else {
+            break;
+        };
+        camera.merge_tile(&tile, &film);
+    }
+    let servers = servers.lock().unwrap().take().unwrap_or_default();
+    for server in servers {
+        let _ = server.join();
+    }
+    eprint!("\rDone                         \n");
+    Ok(())
+}
+
+/// Send one worker the job and then tiles until none are left, and wait for it to
+/// hang up. A tile in hand when the connection fails goes back on the queue.
+fn serve(
+    stream: TcpStream,
+    (args, files): (&[String], &[Vec<u8>]),
+    film_layout: (usize, Option<usize>), // Width and light groups of tile films
+    queue: &Mutex<Queue>,
+    results: &mpsc::Sender<(Tile, Film)>,
+    timeout: Duration,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    let mut input = BufReader::new(&stream);
+    let mut out = BufWriter::new(&stream);
+
+    write_u64(&mut out, args.len() as u64)?;
+    for arg in args {
+        write_u64(&mut out, arg.len() as u64)?;
+        out.write_all(arg.as_bytes())?;
+    }
+    for file in files {
+        write_u64(&mut out, file.len() as u64)?;
+        out.write_all(file)?;
+    }
+
+    loop {
+        let next = {
+            let mut queue = queue.lock().unwrap();
+            match queue.pending.pop_front() {
+                Some(tile) => Some(tile),
+                None if queue.outstanding == 0 => None,
+                None => {
+                    // Other workers hold the last tiles; wait in case one is lost.
+                    drop(queue);
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+            }
+        };
+        let Some(tile) = next else {
+            write_u64(&mut out, NO_MORE_TILES)?;
+            out.flush()?;
+            // Reading ends when the worker hangs up, or at worst after the timeout.
+            let _ = input.read(&mut [0]);
+            return Ok(());
+        };
+
+        match request_tile(&mut input, &mut out, &tile, film_layout) {
+            Ok(film) => {
+                queue.lock().unwrap().outstanding -= 1;
+                // The coordinator only stops listening once every tile is in.
+                let _ = results.send((tile, film));
+            }
+            Err(e) => {
+                queue.lock().unwrap().pending.push_front(tile);
+                return Err(e);
+            }
+        }
+    }
+}
+
+fn request_tile(
+    input: &mut impl Read,
+    out: &mut impl Write,
+    tile: &Tile,
+    (width, light_groups): (usize, Option<usize>),
+) -> io::Result<Film> {
+    write_u64(out, tile.eye as u64)?;
+    write_u64(out, tile.rows.start)?;
+    write_u64(out, tile.rows.end)?;
+    out.flush()?;
+
+    let rows = tile.rows.start as usize..tile.rows.end as usize;
+    Film::read_from(input, width, rows, light_groups)
+}
+
+/// Connect to the coordinator at `address` and render the tiles it sends until it
+/// has no more. `setup` builds the scene and camera from the coordinator's command
+/// line, which must describe the same render as on the coordinator.
+pub fn work<H: Hittable>(
+    address: &str,
+    setup: impl FnOnce(Vec<String>) -> Result<(H, Camera), String>,
+) -> io::Result<()> {
+    let stream = connect(address)?;
+    let mut input = BufReader::new(&stream);
+    let mut out = BufWriter::new(&stream);
+
+    let count = read_u64(&mut input)?;
+    let mut args = (0..count)
+        .map(|_| read_string(&mut input))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    // Save the input files where this worker can load them, and point the command
+    // line at the copies for as long as setting up takes.
+    let dir = std::env::temp_dir().join(format!("raytrace-worker-{}", process::id()));
+    let files = input_file_indices(&args).collect::<Vec<_>>();
+    if !files.is_empty() {
+        fs::create_dir_all(&dir)?;
+    }
+    for (n, i) in files.into_iter().enumerate() {
+        let path = dir.join(n.to_string());
+        fs::write(&path, read_bytes(&mut input, MAX_INPUT_FILE)?)?;
+        args[i] = path.to_string_lossy().into_owned();
+    }
+    let set_up = setup(args);
+    let _ = fs::remove_dir_all(&dir);
+    let (world, mut camera) = set_up.map_err(Error::other)?;
+
+    loop {
+        let eye = read_u64(&mut input)?;
+        if eye == NO_MORE_TILES {
+            return Ok(());
+        }
+        let rows = read_u64(&mut input)?..read_u64(&mut input)?;
+        let tile = Tile {
+            eye: eye as usize,
+            rows,
+        };
+        camera.render_tile(&world, &tile).write_to(&mut out)?;
+        out.flush()?;
+    }
+}
+
+/// Workers may start before the coordinator, so keep trying for a while.
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let mut attempts = 30;
+    loop {
+        match TcpStream::connect(address) {
+            Ok(stream) => return Ok(stream),
+            Err(e) if attempts == 0 => return Err(e),
+            Err(_) => {
+                attempts -= 1;
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+/// The positions in `args` of the values of options naming input files.
+fn input_file_indices(args: &[String]) -> impl Iterator<Item = usize> + '_ {
+    (1..args.len()).filter(|&i| INPUT_FILE_OPTIONS.contains(&args[i - 1].as_str()))
+}
+
+fn read_bytes(input: &mut impl Read, max_len: u64) -> io::Result<Vec<u8>> {
+    let len = read_u64(input)?;
+    if len > max_len {
+        return Err(Error::new(ErrorKind::InvalidData, "message too long"));
+    }
+    let mut bytes = vec![0; len as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(input: &mut impl Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(input, 1 << 20)?)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "argument not UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::BufReader,
+        net::{TcpListener, TcpStream},
+        process,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        camera::Camera,
+        color::Color,
+        distributed::{coordinate, read_string, work},
+        film::read_u64,
+        hittable_list::HittableList,
+        material::Lambertian,
+        sphere::Sphere,
+        vec3::Point3,
+    };
+
+    fn scene() -> (HittableList<'static>, Camera) {
+        let mut world = HittableList::new();
+        world.add(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let mut cam = Camera::new();
+        cam.image_width = 16;
+        cam.samples_per_pixel = 4;
+        (world, cam)
+    }
+
+    #[test]
+    fn test_distributed_render_matches_local_render() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = thread::spawn(move || {
+            let (_, mut cam) = scene();
+            let args = vec![String::from("test")];
+            coordinate(&mut cam, listener, &args, 3, Duration::from_secs(10)).unwrap();
+            cam.image()
+        });
+
+        // A worker that disconnects holding a tile, which must go to another worker.
+        let stream = TcpStream::connect(&address).unwrap();
+        let mut input = BufReader::new(&stream);
+        let count = read_u64(&mut input).unwrap();
+        for _ in 0..count {
+            read_string(&mut input).unwrap();
+        }
+        drop(input);
+        drop(stream);
+
+        let (done, finished) = mpsc::channel();
+        for _ in 0..2 {
+            let (address, done) = (address.clone(), done.clone());
+            thread::spawn(move || done.send(work(&address, |_| Ok(scene())).is_ok()));
+        }
+        let distributed = coordinator.join().unwrap();
+        // Each worker has hung up by the time the coordinator returns, which a
+        // worker left waiting for its next tile would not have.
+        for _ in 0..2 {
+            assert_eq!(Ok(true), finished.recv_timeout(Duration::from_millis(50)));
+        }
+
+        let (world, mut cam) = scene();
+        let local = cam.render_progressive(&world, |_, _| {});
+        for y in 0..local.height() {
+            for x in 0..local.width() {
+                assert_eq!(local.pixel(x, y), distributed.pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_workers_are_sent_input_files() {
+        let path = std::env::temp_dir().join(format!("raytrace-test-lut-{}", process::id()));
+        fs::write(&path, "LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").unwrap();
+        let args: Vec<String> = ["test", "--lut", path.to_str().unwrap()]
+            .map(String::from)
+            .to_vec();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let coordinator = thread::spawn(move || {
+            let (_, mut cam) = scene();
+            coordinate(&mut cam, listener, &args, 16, Duration::from_secs(10)).unwrap();
+        });
+        let mut sent = None;
+        work(&address, |args| {
+            sent = Some((args[2].clone(), fs::read_to_string(&args[2]).unwrap()));
+            Ok(scene())
+        })
+        .unwrap();
+        coordinator.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (copy, contents) = sent.unwrap();
+        assert_ne!(path.to_str().unwrap(), copy);
+        assert_eq!("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n", contents);
+    }
+}
@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+use crate::{blue_noise, sampler::hash};
+
+/// Noise added to display-encoded values before they are quantised to 8 bits, which
+/// trades the banding of smooth gradients for fine grain.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    /// Plain truncation.
+    #[default]
+    None,
+    /// White noise with a triangular distribution two steps wide, which makes the
+    /// error independent of the signal.
+    Triangular,
+    /// An 8×8 Bayer matrix, a regular crosshatch pattern.
+    Bayer,
+    /// The blue-noise tile, which has no low-frequency clumps.
+    BlueNoise,
+}
+
+impl FromStr for Dither {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Dither, String> {
+        match s {
+            "none" => Ok(Dither::None),
+            "triangular" => Ok(Dither::Triangular),
+            "bayer" => Ok(Dither::Bayer),
+            "blue-noise" => Ok(Dither::BlueNoise),
+            _ => Err(format!("Unknown dither: {s}")),
+        }
+    }
+}
+
+impl Dither {
+    /// The 8-bit level of an encoded value in [0, 1] for the given channel of the
+    /// pixel at (x, y).
+    pub fn quantize(&self, value: f64, x: usize, y: usize, channel: usize) -> u8 {
+        let value = value.clamp(0.0, 1.0);
+        let offset = match self {
+            Dither::None => return (256.0 * value.min(0.999)) as u8,
+            Dither::Triangular => {
+                let bits = hash(&[x as u64, y as u64, channel as u64]);
+                let u = |bits: u64| (bits >> 11) as f64 / (1u64 << 53) as f64;
+                u(bits) + u(bits.rotate_left(32)) - 1.0
+            }
+            Dither::Bayer => bayer(x, y, channel) - 0.5,
+            // Each channel reads a different part of the tile so that the noise in
+            // them is uncorrelated and stays grey on average.
+            Dither::BlueNoise => {
+                let shift = 23 * channel as u64;
+                blue_noise::value(x as u64 + shift, y as u64 + 2 * shift) - 0.5
+            }
+        };
+        (255.0 * value + 0.5 + offset).floor().clamp(0.0, 255.0) as u8
+    }
+}
+
+/// The threshold in [0, 1) of an 8×8 Bayer matrix at (x, y), from interleaving the
+/// bits of x ^ y and y in reverse order. Channels are offset along the diagonal.
+fn bayer(x: usize, y: usize, channel: usize) -> f64 {
+    let (x, y) = (x + 3 * channel, y + 5 * channel);
+    let mut index = 0;
+    for bit in 0..3 {
+        index = (index << 2) | (((x ^ y) >> bit & 1) << 1) | (y >> bit & 1);
+    }
+    (index as f64 + 0.5) / 64.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dither::Dither;
+
+    #[test]
+    fn test_dithering_preserves_the_mean_level() {
+        let value = 100.3 / 255.0;
+        for dither in [Dither::Triangular, Dither::Bayer, Dither::BlueNoise] {
+            let mut sum = 0.0;
+            for y in 0..64 {
+                for x in 0..64 {
+                    sum += dither.quantize(value, x, y, 1) as f64;
+                }
+            }
+            assert!((sum / 4096.0 - 100.3).abs() < 0.05, "{dither:?}");
+        }
+        assert_eq!(100, Dither::None.quantize(value, 0, 0, 0));
+        assert_eq!(255, Dither::Bayer.quantize(1.0, 7, 7, 2));
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+
+use crate::{colorspace::Chromaticities, image::Image, vec3::Vec3};
+
+/// An image stored in an EXR file as the channels `name.channel` for each of
+/// `channels`, which take the image's colour components in order. An empty name
+/// puts the channels at the top level, as for the main R, G, B and depth Z.
+pub struct Layer {
+    pub name: String,
+    pub channels: &'static [&'static str],
+    pub image: Image,
+}
+
+impl Layer {
+    pub fn new(name: &str, channels: &'static [&'static str], image: Image) -> Layer {
+        Layer {
+            name: name.to_string(),
+            channels,
+            image,
+        }
+    }
+}
+
+/// Write layers of the same size as a single-part, uncompressed, scanline OpenEXR
+/// file with 32-bit float channels, tagged with the chromaticities of their RGB.
+pub fn write_exr(
+    out: &mut impl Write,
+    layers: &[Layer],
+    chromaticities: &Chromaticities,
+) -> io::Result<()> {
+    let (width, height) = layers
+        .first()
+        .map_or((0, 0), |l| (l.image.width(), l.image.height()));
+
+    // EXR stores channels sorted by name, each as a run of one scanline's values.
+    let mut channels: Vec<(String, &Image, usize)> = Vec::new();
+    for layer in layers {
+        for (component, channel) in layer.channels.iter().enumerate() {
+            let name = if layer.name.is_empty() {
+                channel.to_string()
+            } else {
+                format!("{}.{channel}", layer.name)
+            };
+            channels.push((name, &layer.image, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = Vec::new();
+    header.extend(0x762f3101u32.to_le_bytes());
+    header.extend(2u32.to_le_bytes()); // Version 2, single-part scanline
+
+    let mut chlist = Vec::new();
+    for (name, _, _) in &channels {
+        chlist.extend(name.as_bytes());
+        chlist.push(0);
+        chlist.extend(2i32.to_le_bytes()); // FLOAT
+        chlist.extend([0, 0, 0, 0]); // pLinear and reserved
+        chlist.extend(1i32.to_le_bytes()); // x and y sampling
+        chlist.extend(1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    attribute(&mut header, "channels", "chlist", &chlist);
+    let c = chromaticities;
+    let xy: Vec<u8> = [c.red, c.green, c.blue, c.white]
+        .iter()
+        .flat_map(|&(x, y)| [x as f32, y as f32])
+        .flat_map(f32::to_le_bytes)
+        .collect();
+    attribute(&mut header, "chromaticities", "chromaticities", &xy);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // A table of where each scanline block starts, then the blocks.
+    let block_size = 8 + 4 * width * channels.len();
+    let first_block = header.len() + 8 * height;
+    for y in 0..height {
+        header.extend(((first_block + y * block_size) as u64).to_le_bytes());
+    }
+    out.write_all(&header)?;
+
+    let mut block = Vec::with_capacity(block_size);
+    for y in 0..height {
+        block.clear();
+        block.extend((y as i32).to_le_bytes());
+        block.extend(((block_size - 8) as i32).to_le_bytes());
+        for (_, image, component) in &channels {
+            for x in 0..width {
+                let c = Vec3::from(image.pixel(x, y).clone());
+                let value = [c.x, c.y, c.z][*component] as f32;
+                block.extend(value.to_le_bytes());
+            }
+        }
+        out.write_all(&block)?;
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        colorspace::ColorSpace,
+        exr::{Layer, write_exr},
+        image::Image,
+    };
+
+    #[test]
+    fn test_scanline_offsets_point_at_blocks() {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(2, 1, Color::new(0.25, 0.5, 1.0));
+        let layers = [
+            Layer::new("", &["R", "G", "B"], image),
+            Layer::new("", &["Z"], Image::new(3, 2)),
+        ];
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, &layers, &ColorSpace::Srgb.chromaticities()).unwrap();
+
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap()) as usize;
+        let i32_at = |i: usize| i32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let table = bytes.len() - 2 * (8 + 4 * 3 * 4) - 16;
+        let second = u64_at(table + 8);
+        assert_eq!(1, i32_at(second));
+        assert_eq!(4 * 3 * 4, i32_at(second + 4));
+        // Channels are sorted B, G, R, Z; the last pixel of the R run is (2, 1).
+        assert_eq!(1.0, f32_at(second + 8 + 8));
+        assert_eq!(0.25, f32_at(second + 8 + 2 * 12 + 8));
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    io::{self, Error, ErrorKind, Read, Write},
+    ops::Range,
+};
+
+use crate::{aov::AovSums, color::Color, image::Image, vec3::Vec3};
+
+/// Per-pixel sums that successive render passes add samples to, for a whole image
+/// or a band of its rows.
+#[derive(Default)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    first_row: usize,
+    pixels: Vec<FilmPixel>,
+}
+
+#[derive(Clone, Default)]
+pub struct FilmPixel {
+    pub color_sum: Color,
+    pub weight_sum: f64,
+    pub coverage_sum: f64, // Filter-weighted count of the samples whose camera ray hit something
+    pub samples: usize,
+    pub converged: bool,
+    pub stats: PixelStats,
+    pub aovs: Option<Box<AovSums>>,
+}
+
+impl Film {
+    /// A film covering only the given rows of an image.
+    pub fn band(width: usize, rows: Range<usize>) -> Film {
+        Film {
+            width,
+            height: rows.len(),
+            first_row: rows.start,
+            pixels: vec![FilmPixel::default(); width * rows.len()],
+        }
+    }
+
+    /// Give every pixel AOV sums with the given number of light groups.
+    pub fn with_aovs(mut self, light_groups: usize) -> Film {
+        for pixel in &mut self.pixels {
+            pixel.aovs = Some(Box::new(AovSums::new(light_groups)));
+        }
+        self
+    }
+
+    /// The image rows the film covers.
+    pub fn rows(&self) -> Range<usize> {
+        self.first_row..self.first_row + self.height
+    }
+
+    /// The pixel at (x, y) in image coordinates.
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut FilmPixel {
+        &mut self.pixels[(y - self.first_row) * self.width + x]
+    }
+
+    /// Overwrite the rows covered by `band` with its pixels.
+    pub fn paste(&mut self, band: &Film) {
+        assert!(
+            band.width == self.width
+                && band.first_row >= self.first_row
+                && band.rows().end <= self.rows().end,
+            "band outside the film"
+        );
+        let start = (band.first_row - self.first_row) * self.width;
+        self.pixels[start..start + band.pixels.len()].clone_from_slice(&band.pixels);
+    }
+
+    /// Each pixel is the filter-weighted mean of its samples so far.
+    pub fn image(&self) -> Image {
+        self.map(|p| {
+            if p.weight_sum != 0.0 {
+                (1.0 / p.weight_sum) * p.color_sum.clone()
+            } else {
+                Color::new(0.0, 0.0, 0.0)
+            }
+        })
+    }
+
+    /// The fraction of each pixel covered by the scene rather than the background,
+    /// as a grey level. Filters with negative lobes can push the ratio outside
+    /// [0, 1], so it is clamped.
+    pub fn coverage(&self) -> Image {
+        self.map(|p| {
+            let alpha = if p.weight_sum != 0.0 {
+                (p.coverage_sum / p.weight_sum).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            Color::new(alpha, alpha, alpha)
+        })
+    }
+
+    /// The number of samples each pixel received as a fraction of `max_samples`,
+    /// as a grey level.
+    pub fn sample_counts(&self, max_samples: usize) -> Image {
+        self.map(|p| {
+            let fraction = p.samples as f64 / max_samples as f64;
+            Color::new(fraction, fraction, fraction)
+        })
+    }
+
+    /// The standard error of each pixel's mean luminance, as a grey level.
+    pub fn standard_errors(&self) -> Image {
+        self.map(|p| {
+            let error = p.stats.standard_error();
+            Color::new(error, error, error)
+        })
+    }
+
+    /// Serialise the sums exactly, so a film read back continues as if never saved.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write_u64(out, self.width as u64)?;
+        write_u64(out, self.height as u64)?;
+        write_u64(out, self.first_row as u64)?;
+        for p in &self.pixels {
+            let color = Vec3::from(p.color_sum.clone());
+            let stats = &p.stats;
+            for value in [
+                color.x,
+                color.y,
+                color.z,
+                p.weight_sum,
+                p.coverage_sum,
+                stats.weight,
+                stats.weight_squared,
+                stats.mean,
+                stats.m2,
+            ] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            write_u64(out, p.samples as u64)?;
+            out.write_all(&[p.converged as u8, p.aovs.is_some() as u8])?;
+            if let Some(aovs) = &p.aovs {
+                aovs.write_to(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a film written by `write_to`, which must have the given width and cover
+    /// the given rows, and whose pixels must have AOV sums with `light_groups`
+    /// groups, or none if that is `None`. Everything is checked before it is
+    /// trusted, as the data may come from another machine.
+    pub fn read_from(
+        input: &mut impl Read,
+        width: usize,
+        rows: Range<usize>,
+        light_groups: Option<usize>,
+    ) -> io::Result<Film> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let size = (read_u64(input)?, read_u64(input)?, read_u64(input)?);
+        if size != (width as u64, rows.len() as u64, rows.start as u64) {
+            return Err(invalid("film has the wrong size"));
+        }
+        let (height, first_row) = (rows.len(), rows.start);
+        let count = width
+            .checked_mul(height)
+            .ok_or_else(|| invalid("film too large"))?;
+        let mut pixels = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut values = [0.0; 9];
+            for value in &mut values {
+                *value = f64::from_bits(read_u64(input)?);
+            }
+            let samples = read_u64(input)? as usize;
+            let mut flags = [0; 2];
+            input.read_exact(&mut flags)?;
+            let aovs = match (flags[1], light_groups) {
+                (0, None) => None,
+                (1, Some(groups)) => Some(Box::new(AovSums::read_from(input, groups)?)),
+                _ => return Err(invalid("film AOVs do not match the render")),
+            };
+            pixels.push(FilmPixel {
+                color_sum: Color::new(values[0], values[1], values[2]),
+                weight_sum: values[3],
+                coverage_sum: values[4],
+                samples,
+                converged: flags[0] != 0,
+                stats: PixelStats {
+                    weight: values[5],
+                    weight_squared: values[6],
+                    mean: values[7],
+                    m2: values[8],
+                },
+                aovs,
+            });
+        }
+        Ok(Film {
+            width,
+            height,
+            first_row,
+            pixels,
+        })
+    }
+
+    /// An image of a quantity computed from each pixel's AOV sums, black when the
+    /// film has none.
+    pub fn aov_image(&self, f: impl Fn(&AovSums, &FilmPixel) -> Color) -> Image {
+        self.map(|p| p.aovs.as_ref().map_or(Color::default(), |aovs| f(aovs, p)))
+    }
+
+    fn map(&self, f: impl Fn(&FilmPixel) -> Color) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.set_pixel(x, y, f(&self.pixels[y * self.width + x]));
+            }
+        }
+        image
+    }
+}
+
+/// Running weighted moments of a pixel's sample luminances.
+#[derive(Clone, Default)]
+pub struct PixelStats {
+    weight: f64,
+    weight_squared: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl PixelStats {
+    /// Add a sample with West's weighted form of Welford's update.
+    pub fn add(&mut self, value: f64, weight: f64) {
+        // Negative filter lobes still count as evidence about the pixel.
+        let weight = weight.abs();
+        if weight == 0.0 {
+            return;
+        }
+        self.weight += weight;
+        self.weight_squared += weight * weight;
+        let delta = value - self.mean;
+        self.mean += weight / self.weight * delta;
+        self.m2 += weight * delta * (value - self.mean);
+    }
+
+    /// Standard error of the mean, infinite until there are enough samples to tell.
+    pub fn standard_error(&self) -> f64 {
+        self.error_with_prior(0.0)
+    }
+
+    /// Standard error of the mean relative to the mean itself, for deciding when the
+    /// pixel has converged. Very dark pixels are measured against a small floor so
+    /// they can still converge. The variance counts one extra virtual sample as far
+    /// from the mean as the mean itself, so a pixel whose first samples happen to
+    /// agree, such as one that has yet to find a small light, needs about one sample
+    /// per unit of 1 / error before it stops.
+    pub fn relative_error(&self) -> f64 {
+        let mean = self.mean.max(0.01);
+        self.error_with_prior(mean * mean) / mean
+    }
+
+    /// Standard error of the mean with the squared deviation of one virtual
+    /// sample of average weight added to the variance.
+    fn error_with_prior(&self, squared_deviation: f64) -> f64 {
+        let effective_samples = self.weight * self.weight / self.weight_squared;
+        if effective_samples < 2.0 {
+            return f64::INFINITY;
+        }
+        let prior = self.weight / effective_samples * squared_deviation;
+        let variance = (self.m2 + prior) / self.weight;
+        (variance / (effective_samples - 1.0)).sqrt()
+    }
+}
+
+pub fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, film::Film};
+
+    #[test]
+    fn test_film_round_trips_exactly() {
+        let mut film = Film::band(3, 0..2);
+        let pixel = film.pixel_mut(2, 1);
+        pixel.color_sum = Color::new(0.1, 0.2, 1.0 / 3.0);
+        pixel.weight_sum = 0.7;
+        pixel.coverage_sum = 0.35;
+        pixel.samples = 5;
+        pixel.converged = true;
+        pixel.stats.add(0.4, 0.7);
+
+        let mut bytes = Vec::new();
+        film.write_to(&mut bytes).unwrap();
+        assert!(Film::read_from(&mut bytes.as_slice(), 3, 1..3, None).is_err());
+        assert!(Film::read_from(&mut bytes.as_slice(), 3, 0..2, Some(1)).is_err());
+        let read = Film::read_from(&mut bytes.as_slice(), 3, 0..2, None).unwrap();
+        assert_eq!(0..2, read.rows());
+        assert_eq!(film.image().pixel(2, 1), read.image().pixel(2, 1));
+        assert_eq!(&Color::new(0.5, 0.5, 0.5), read.coverage().pixel(2, 1));
+        film.pixel_mut(0, 0).weight_sum = 0.5;
+        film.pixel_mut(0, 0).coverage_sum = 0.75;
+        assert_eq!(&Color::new(1.0, 1.0, 1.0), film.coverage().pixel(0, 0));
+        assert_eq!(
+            film.sample_counts(5).pixel(2, 1),
+            read.sample_counts(5).pixel(2, 1)
+        );
+        assert!(read.pixels[5].converged);
+        assert_eq!(film.pixels[5].stats.m2, read.pixels[5].stats.m2);
+    }
+
+    #[test]
+    fn test_film_aovs_must_match_the_render() {
+        let mut bytes = Vec::new();
+        Film::band(2, 0..1)
+            .with_aovs(2)
+            .write_to(&mut bytes)
+            .unwrap();
+        let read = |light_groups| Film::read_from(&mut bytes.as_slice(), 2, 0..1, light_groups);
+        assert!(read(None).is_err());
+        assert!(read(Some(3)).is_err());
+        assert_eq!(
+            2,
+            read(Some(2)).unwrap().pixels[1]
+                .aovs
+                .as_ref()
+                .unwrap()
+                .lights
+                .len()
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use crate::vec3::Vec3;
+
+/// Pixel reconstruction filters. Each has the radius, in pixels, it is usually
+/// used with.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// Uniform over the pixel, radius 0.5.
+    #[default]
+    Box,
+    /// Linear falloff, radius 1.
+    Tent,
+    /// Gaussian with a standard deviation of 0.5, radius 1.5.
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3, radius 2.
+    Mitchell,
+    /// Sinc windowed by a wider sinc, radius 3.
+    Lanczos,
+    /// Four-term Blackman-Harris window, radius 2.
+    BlackmanHarris,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Filter, String> {
+        match s {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            "lanczos" => Ok(Filter::Lanczos),
+            "blackman-harris" => Ok(Filter::BlackmanHarris),
+            _ => Err(format!("Unknown filter: {s}")),
+        }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell | Filter::BlackmanHarris => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+
+    /// The one-dimensional filter at offset `x` pixels; the 2D filter is the product
+    /// of the values for x and y.
+    pub fn evaluate(&self, x: f64) -> f64 {
+        let r = self.radius();
+        let x = x.abs();
+        if x > r {
+            return 0.0;
+        }
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => r - x,
+            Filter::Gaussian => {
+                let gaussian = |x: f64| (-2.0 * x * x).exp(); // sigma = 0.5
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            Filter::Mitchell => {
+                const B: f64 = 1.0 / 3.0;
+                const C: f64 = 1.0 / 3.0;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * B - 6.0 * C) * x * x * x
+                        + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+                        + (6.0 - 2.0 * B))
+                        / 6.0
+                } else {
+                    ((-B - 6.0 * C) * x * x * x
+                        + (6.0 * B + 30.0 * C) * x * x
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C))
+                        / 6.0
+                }
+            }
+            Filter::Lanczos => sinc(x) * sinc(x / r),
+            Filter::BlackmanHarris => {
+                let t = 0.5 * (x / r + 1.0);
+                0.35875 - 0.48829 * (2.0 * PI * t).cos() + 0.14128 * (4.0 * PI * t).cos()
+                    - 0.01168 * (6.0 * PI * t).cos()
+            }
+        }
+    }
+
+    /// Tabulate the filter for importance sampling.
+    pub fn sampler(&self) -> FilterSampler {
+        const BINS: usize = 64;
+        let radius = self.radius();
+        let bin_width = 2.0 * radius / BINS as f64;
+
+        let mut cdf = Vec::with_capacity(BINS);
+        let mut total = 0.0;
+        for bin in 0..BINS {
+            let x = -radius + (bin as f64 + 0.5) * bin_width;
+            total += self.evaluate(x).abs();
+            cdf.push(total);
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        FilterSampler {
+            filter: *self,
+            cdf,
+            bin_width,
+            abs_integral: total * bin_width,
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Draws pixel offsets distributed like the absolute value of a filter. The
+/// accompanying weight carries the sign of the filter lobe and corrects for the
+/// tabulation, so a pixel is the weighted mean of its samples.
+#[derive(Default)]
+pub struct FilterSampler {
+    filter: Filter,
+    cdf: Vec<f64>,
+    bin_width: f64,
+    abs_integral: f64,
+}
+
+impl FilterSampler {
+    /// Map a point of the unit square to an offset from the pixel centre and its weight.
+    pub fn sample(&self, u: (f64, f64)) -> (Vec3, f64) {
+        if self.filter == Filter::Box || self.cdf.is_empty() {
+            return (Vec3::new(u.0 - 0.5, u.1 - 0.5, 0.), 1.0);
+        }
+        let (x, weight_x) = self.sample_1d(u.0);
+        let (y, weight_y) = self.sample_1d(u.1);
+        (Vec3::new(x, y, 0.), weight_x * weight_y)
+    }
+
+    fn sample_1d(&self, u: f64) -> (f64, f64) {
+        // Invert the piecewise constant CDF, reusing u's position within the chosen
+        // bin so well spread sample values stay well spread.
+        let bin = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .min(self.cdf.len() - 1);
+        let below = if bin > 0 { self.cdf[bin - 1] } else { 0.0 };
+        let within = ((u - below) / (self.cdf[bin] - below)).clamp(0.0, 1.0);
+        let x = -self.filter.radius() + (bin as f64 + within) * self.bin_width;
+
+        let bin_center = -self.filter.radius() + (bin as f64 + 0.5) * self.bin_width;
+        let pdf = self.filter.evaluate(bin_center).abs() / self.abs_integral;
+        (x, self.filter.evaluate(x) / pdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{filter::Filter, util::random_f64};
+
+    #[test]
+    fn test_mitchell_integrates_to_one() {
+        let n = 10000;
+        let dx = 4.0 / n as f64;
+        let integral: f64 = (0..n)
+            .map(|i| Filter::Mitchell.evaluate(-2.0 + (i as f64 + 0.5) * dx) * dx)
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_samples_stay_within_radius() {
+        let sampler = Filter::Lanczos.sampler();
+        for _ in 0..1000 {
+            let (offset, weight) = sampler.sample((random_f64(), random_f64()));
+            assert!(offset.x.abs() <= 3.0 && offset.y.abs() <= 3.0);
+            assert!(weight.is_finite());
+        }
+    }
+}
@@ -3,32 +3,62 @@ use crate::material::Material;
 use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
 
+#[derive(Clone)]
 pub struct HitRecord<'a> {
     pub p: Point3,
-    pub normal: Vec3,
+    pub normal: Vec3,           // shading normal, may be perturbed by the material
+    pub geometric_normal: Vec3, // true surface normal, used for front_face and offsets
+    pub tangent: Vec3,          // unit direction of increasing u
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
     pub mat: &'a dyn Material,
+    pub object_id: u32,   // 1 + the index of the object in the top-level list
+    pub material_id: u32, // Numbers the top-level list's materials from 1 as they were added
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn new(p: Point3, normal: Vec3, t: f64, mat: &'a dyn Material) -> HitRecord<'a> {
+    pub fn new(
+        p: Point3,
+        normal: Vec3,
+        tangent: Vec3,
+        t: f64,
+        mat: &'a dyn Material,
+    ) -> HitRecord<'a> {
         HitRecord {
             p,
             normal,
+            geometric_normal: normal,
+            tangent,
             t,
+            u: 0.0,
+            v: 0.0,
             front_face: true,
-            mat: mat,
+            mat,
+            object_id: 0,
+            material_id: 0,
         }
     }
     pub fn set_face_normal(&mut self, r: &Ray) {
-        self.front_face = Vec3::dot(r.direction, self.normal) < 0.0;
+        self.front_face = Vec3::dot(r.direction, self.geometric_normal) < 0.0;
         if !self.front_face {
             self.normal = -self.normal;
+            self.geometric_normal = -self.geometric_normal;
         }
     }
+    /// The unit vector completing the tangent frame around the shading normal.
+    pub fn bitangent(&self) -> Vec3 {
+        Vec3::cross(self.normal, self.tangent)
+    }
 }
 
 pub trait Hittable {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
+
+    /// Every material the object's hits can have, for numbering them in the
+    /// material ID AOV. Hits on materials not listed get ID 0.
+    fn materials(&self) -> Vec<&dyn Material> {
+        Vec::new()
+    }
 }
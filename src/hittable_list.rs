@@ -1,32 +1,103 @@
+use std::collections::HashMap;
+
 use crate::hittable::{HitRecord, Hittable};
 use crate::interval::Interval;
+use crate::material::Material;
 use crate::ray::Ray;
 
 pub struct HittableList<'a> {
     objects: Vec<Box<dyn Hittable + 'a>>, // object has no components with shorter lifetime
+    // Material IDs by material address. Numbering in the order objects are added
+    // gives every process building the scene the same IDs.
+    material_ids: HashMap<usize, u32>,
 }
 
 impl<'a> HittableList<'a> {
     pub fn new() -> HittableList<'a> {
         HittableList {
             objects: Vec::new(),
+            material_ids: HashMap::new(),
         }
     }
     pub fn add(&mut self, object: impl Hittable + 'a) {
-        self.objects.push(Box::new(object));
+        // Boxed first, so the materials are at the addresses hits will report.
+        let object: Box<dyn Hittable + 'a> = Box::new(object);
+        for mat in object.materials() {
+            let next = self.material_ids.len() as u32 + 1;
+            self.material_ids.entry(address(mat)).or_insert(next);
+        }
+        self.objects.push(object);
     }
 }
 
+fn address(mat: &dyn Material) -> usize {
+    (mat as *const dyn Material).cast::<()>() as usize
+}
+
 impl<'a> Hittable for HittableList<'a> {
     fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let mut rec = None;
         let mut closest_so_far = ray_t.max;
-        for object in self.objects.iter() {
-            if let Some(hrec) = object.hit(r, Interval::new(ray_t.min, closest_so_far)) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(mut hrec) = object.hit(r, Interval::new(ray_t.min, closest_so_far)) {
                 closest_so_far = hrec.t;
+                hrec.object_id = index as u32 + 1;
+                hrec.material_id = self
+                    .material_ids
+                    .get(&address(hrec.mat))
+                    .copied()
+                    .unwrap_or(0);
                 rec = Some(hrec);
             }
         }
         rec
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        self.objects
+            .iter()
+            .flat_map(|object| object.materials())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        hittable_list::HittableList,
+        interval::Interval,
+        material::Lambertian,
+        ray::Ray,
+        sphere::Sphere,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn test_materials_are_numbered_in_the_order_they_are_added() {
+        let grey = || Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let mut world = HittableList::new();
+        for x in [-2.0, 0.0, 2.0] {
+            world.add(Sphere::new(Point3::new(x, 0.0, -2.0), 0.5, grey()));
+        }
+        let mut nested = HittableList::new();
+        nested.add(Sphere::new(Point3::new(0.0, 2.0, -2.0), 0.5, grey()));
+        world.add(nested);
+
+        let id_at = |x, y| {
+            let r = Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0));
+            let rec = world.hit(&r, Interval::new(0.001, f64::INFINITY)).unwrap();
+            rec.material_id
+        };
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            vec![
+                id_at(-2.0, 0.0),
+                id_at(0.0, 0.0),
+                id_at(2.0, 0.0),
+                id_at(0.0, 2.0)
+            ]
+        );
+    }
 }
@@ -0,0 +1,169 @@
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+};
+
+use crate::{color::Color, vec3::Vec3};
+
+/// A grid of colours with (0, 0) at the upper left.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    /// A black image of the given size.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    /// Load a plain (P3) or binary (P6) PPM file. Channel values are scaled to [0, 1]
+    /// without any transfer function, so data maps such as normal maps stay linear.
+    pub fn load_ppm(path: impl AsRef<Path>) -> io::Result<Image> {
+        Image::parse_ppm(&fs::read(path)?)
+    }
+
+    fn parse_ppm(bytes: &[u8]) -> io::Result<Image> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+
+        // Header: magic, width, height and maxval separated by whitespace, with
+        // `#` comments running to the end of the line.
+        let mut pos = 0;
+        let mut fields = Vec::new();
+        while fields.len() < 4 {
+            while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+                if bytes[pos] == b'#' {
+                    while pos < bytes.len() && bytes[pos] != b'\n' {
+                        pos += 1;
+                    }
+                } else {
+                    pos += 1;
+                }
+            }
+            let start = pos;
+            while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated PPM header"));
+            }
+            fields.push(String::from_utf8_lossy(&bytes[start..pos]).into_owned());
+        }
+
+        let number = |s: &str| s.parse::<usize>().map_err(|_| invalid("bad PPM header"));
+        let width = number(&fields[1])?;
+        let height = number(&fields[2])?;
+        if width == 0 || height == 0 {
+            return Err(invalid("empty PPM image"));
+        }
+        let maxval = number(&fields[3])?;
+        if maxval == 0 || maxval > 65535 {
+            return Err(invalid("bad PPM maxval"));
+        }
+        let scale = 1.0 / maxval as f64;
+
+        let samples: Vec<usize> = match fields[0].as_str() {
+            "P3" => String::from_utf8_lossy(&bytes[pos..])
+                .split_ascii_whitespace()
+                .map(number)
+                .collect::<io::Result<_>>()?,
+            "P6" => {
+                // Exactly one whitespace byte separates the header from the raster.
+                let raster = bytes.get(pos + 1..).unwrap_or_default();
+                if maxval < 256 {
+                    raster.iter().map(|&b| b as usize).collect()
+                } else {
+                    raster
+                        .chunks_exact(2)
+                        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                        .collect()
+                }
+            }
+            _ => return Err(invalid("unsupported PPM format")),
+        };
+        let raster_len = (width.checked_mul(height))
+            .and_then(|count| count.checked_mul(3))
+            .ok_or_else(|| invalid("PPM image too large"))?;
+        if samples.len() < raster_len {
+            return Err(invalid("truncated PPM raster"));
+        }
+
+        let pixels = samples[..raster_len]
+            .chunks_exact(3)
+            .map(|c| {
+                Color::new(
+                    c[0] as f64 * scale,
+                    c[1] as f64 * scale,
+                    c[2] as f64 * scale,
+                )
+            })
+            .collect();
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixel(&self, x: usize, y: usize) -> &Color {
+        &self.pixels[y * self.width + x]
+    }
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Write the image as a plain PPM with no transfer function, the inverse of
+    /// `load_ppm` for data such as sample count maps.
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let byte = |c: f64| (255.0 * c.clamp(0.0, 1.0)).round() as u8;
+        let mut out = format!("P3\n{} {}\n255\n", self.width, self.height);
+        for color in &self.pixels {
+            let c = Vec3::from(color.clone());
+            out += &format!("{} {} {}\n", byte(c.x), byte(c.y), byte(c.z));
+        }
+        fs::write(path, out)
+    }
+
+    /// Copy `other` into this image with its upper left corner at (x0, y0).
+    pub fn paste(&mut self, other: &Image, x0: usize, y0: usize) {
+        for y in 0..other.height {
+            for x in 0..other.width {
+                self.set_pixel(x0 + x, y0 + y, other.pixel(x, y).clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, image::Image};
+
+    #[test]
+    fn test_parse_plain_ppm() {
+        let image = Image::parse_ppm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+        assert_eq!((2, 1), (image.width(), image.height()));
+        assert_eq!(&Color::new(0.0, 0.0, 1.0), image.pixel(1, 0));
+    }
+
+    #[test]
+    fn test_parse_binary_ppm() {
+        let image = Image::parse_ppm(b"P6 1 1 255\n\xff\x00\xff").unwrap();
+        assert_eq!(&Color::new(1.0, 0.0, 1.0), image.pixel(0, 0));
+        assert!(Image::parse_ppm(b"P6 0 0 255\n").is_err());
+        assert!(Image::parse_ppm(b"P3 2 0 255\n").is_err());
+        let huge = format!("P6 {} {} 255\n", usize::MAX / 2, 3);
+        assert!(Image::parse_ppm(huge.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use std::{fs, io, path::Path};
+
+use crate::{material::Dielectric, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+/// One refracting surface of a lens prescription, or the aperture stop when
+/// `curvature_radius` is zero. Lengths are in millimetres.
+struct LensElement {
+    curvature_radius: f64,
+    thickness: f64, // distance to the next surface towards the film
+    eta: f64,       // index of refraction behind the surface, 0 or 1 for air
+    aperture_radius: f64,
+}
+
+/// Axis-aligned bounds on the plane of the rear lens element.
+#[derive(Clone, Copy)]
+struct Bounds {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+impl Bounds {
+    const EMPTY: Bounds = Bounds {
+        min_x: f64::INFINITY,
+        min_y: f64::INFINITY,
+        max_x: f64::NEG_INFINITY,
+        max_y: f64::NEG_INFINITY,
+    };
+
+    fn include(&mut self, x: f64, y: f64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+    fn area(&self) -> f64 {
+        if self.min_x > self.max_x || self.min_y > self.max_y {
+            return 0.0;
+        }
+        (self.max_x - self.min_x) * (self.max_y - self.min_y)
+    }
+}
+
+/// A sequence of spherical lens elements in front of the film, as found in lens
+/// design tables. Rays are traced through every surface, so distortion, vignetting
+/// and focus breathing come out of the optics.
+///
+/// The system is laid out along the z axis with the film at z = 0 and the scene
+/// towards negative z, in millimetres.
+pub struct LensSystem {
+    elements: Vec<LensElement>,  // front (scene side) to rear (film side)
+    rear_thickness: f64,         // rear element to film as prescribed, before focusing
+    focused: Option<(f64, f64)>, // focus distance and film diagonal last focused for
+    exit_pupil: Vec<Bounds>,     // indexed by distance from the film centre
+    film_radius: f64,
+    max_pupil_area: f64,
+    axial_transmittance: f64,
+}
+
+impl LensSystem {
+    /// Read a prescription with one surface per line: curvature radius, thickness,
+    /// index of refraction and aperture diameter, all in millimetres, front element
+    /// first. Lines starting with `#` are comments.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<LensSystem> {
+        LensSystem::parse(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(prescription: &str) -> Result<LensSystem, String> {
+        let mut elements = Vec::new();
+        for line in prescription.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("Bad lens element: {line}"))?;
+            let [curvature_radius, thickness, eta, aperture] = values[..] else {
+                return Err(format!("Expected 4 values per lens element: {line}"));
+            };
+            elements.push(LensElement {
+                curvature_radius,
+                thickness,
+                eta,
+                aperture_radius: aperture / 2.0,
+            });
+        }
+        let Some(rear) = elements.last() else {
+            return Err(String::from("Lens prescription has no elements"));
+        };
+
+        Ok(LensSystem {
+            rear_thickness: rear.thickness,
+            focused: None,
+            elements,
+            exit_pupil: Vec::new(),
+            film_radius: 0.0,
+            max_pupil_area: 0.0,
+            axial_transmittance: 1.0,
+        })
+    }
+
+    /// Move the film so objects at `focus_distance` millimetres from it are sharp,
+    /// and work out which part of the rear element passes light to each film radius.
+    /// Focusing again with the same settings does nothing, as this traces many rays.
+    pub fn focus(&mut self, focus_distance: f64, film_diagonal: f64) {
+        if self.focused == Some((focus_distance, film_diagonal)) {
+            return;
+        }
+        self.focused = Some((focus_distance, film_diagonal));
+
+        // Focus from the prescribed film position, not wherever the last focus left it.
+        let rear_thickness = self.rear_thickness;
+        if let Some(rear) = self.elements.last_mut() {
+            rear.thickness = rear_thickness;
+        }
+        let delta = self.thick_lens_focus_delta(focus_distance);
+        if let Some(rear) = self.elements.last_mut()
+            && delta.is_finite()
+        {
+            rear.thickness += delta;
+        }
+
+        self.film_radius = film_diagonal / 2.0;
+        self.exit_pupil = (0..64)
+            .map(|i| {
+                let r0 = i as f64 / 64.0 * self.film_radius;
+                let r1 = (i + 1) as f64 / 64.0 * self.film_radius;
+                self.bound_exit_pupil(r0, r1)
+            })
+            .collect();
+        self.max_pupil_area = self.exit_pupil.iter().map(Bounds::area).fold(0.0, f64::max);
+
+        let axial = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        self.axial_transmittance = self.trace_from_film(axial).map_or(1.0, |(_, t)| t);
+    }
+
+    /// Generate a ray from the film point (x, y) towards the scene, or `None` when the
+    /// lens blocks it. Rays are culled in proportion to the cos⁴ falloff, exit pupil
+    /// size and Fresnel losses relative to the film centre, so surviving rays need no
+    /// further weighting and the centre of the image is exposed as with a thin lens.
+    pub fn sample_ray(&self, x: f64, y: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let r = x.hypot(y);
+        let index = ((r / self.film_radius * self.exit_pupil.len() as f64) as usize)
+            .min(self.exit_pupil.len().checked_sub(1)?);
+        let bounds = self.exit_pupil[index];
+        let area = bounds.area();
+        if area == 0.0 {
+            return None;
+        }
+
+        // Sample the pupil bounds, which were computed along +x, rotated to the film point.
+        let (u, v) = sampler.get_2d();
+        let px = bounds.min_x + u * (bounds.max_x - bounds.min_x);
+        let py = bounds.min_y + v * (bounds.max_y - bounds.min_y);
+        let (sin, cos) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+        let rear = Vec3::new(cos * px - sin * py, sin * px + cos * py, -self.rear_z());
+
+        let film = Vec3::new(x, y, 0.0);
+        let direction = Vec3::unit_vector(rear - film);
+        let cos4_theta = direction.z.powi(4);
+
+        let (ray, transmittance) = self.trace_from_film(Ray::new(film, direction))?;
+        let relative_transmittance = transmittance / self.axial_transmittance;
+        if sampler.get_1d() < cos4_theta * relative_transmittance * area / self.max_pupil_area {
+            Some(ray)
+        } else {
+            None
+        }
+    }
+
+    /// Distance from the film to the rear element.
+    fn rear_z(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+    /// Distance from the film to the front element.
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    /// Trace a ray leaving the film through every surface to the scene. Returns the
+    /// ray leaving the front element and the fraction of light Fresnel reflection
+    /// lets through.
+    fn trace_from_film(&self, mut r: Ray) -> Option<(Ray, f64)> {
+        let mut transmittance = 1.0;
+        let mut element_z = 0.0;
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            element_z -= element.thickness;
+            let eta_t = if i > 0 {
+                air_if_zero(self.elements[i - 1].eta)
+            } else {
+                1.0
+            };
+            transmittance *=
+                self.pass_surface(&mut r, element, element_z, air_if_zero(element.eta), eta_t)?;
+        }
+        Some((r, transmittance))
+    }
+
+    /// Trace a ray arriving from the scene through every surface to the film side.
+    fn trace_from_scene(&self, mut r: Ray) -> Option<Ray> {
+        let mut element_z = -self.front_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let eta_i = if i > 0 {
+                air_if_zero(self.elements[i - 1].eta)
+            } else {
+                1.0
+            };
+            self.pass_surface(&mut r, element, element_z, eta_i, air_if_zero(element.eta))?;
+            element_z += element.thickness;
+        }
+        Some(r)
+    }
+
+    /// Intersect one surface whose vertex is at `element_z`, clip to its aperture and
+    /// refract from `eta_i` into `eta_t`. Returns the Fresnel transmittance.
+    fn pass_surface(
+        &self,
+        r: &mut Ray,
+        element: &LensElement,
+        element_z: f64,
+        eta_i: f64,
+        eta_t: f64,
+    ) -> Option<f64> {
+        if element.curvature_radius == 0.0 {
+            // The aperture stop is a flat opening.
+            let t = (element_z - r.origin.z) / r.direction.z;
+            if !(t >= 0.0 && t.is_finite()) {
+                return None;
+            }
+            r.origin = r.at(t);
+            let inside = r.origin.x.hypot(r.origin.y) <= element.aperture_radius;
+            return inside.then_some(1.0);
+        }
+
+        let (t, normal) = intersect_spherical(element.curvature_radius, element_z, r)?;
+        let p = r.at(t);
+        if p.x.hypot(p.y) > element.aperture_radius {
+            return None;
+        }
+
+        let unit_direction = Vec3::unit_vector(r.direction);
+        let ratio = eta_i / eta_t;
+        let cos_theta = Vec3::dot(-unit_direction, normal).min(1.0);
+        if ratio * ratio * (1.0 - cos_theta * cos_theta) > 1.0 {
+            return None; // total internal reflection
+        }
+
+        *r = Ray::new(p, Vec3::refract(unit_direction, normal, ratio));
+        Some(1.0 - Dielectric::reflectance(cos_theta, ratio))
+    }
+
+    /// Principal plane and focal point positions for light arriving from the scene,
+    /// then for light arriving from the film, using rays close to the optical axis.
+    fn cardinal_points(&self) -> ((f64, f64), (f64, f64)) {
+        let x = 0.001 * self.film_radius.max(1.0);
+        let cardinal = |r_in: &Ray, r_out: Option<Ray>| match r_out {
+            Some(r_out) => {
+                let t_focal = -r_out.origin.x / r_out.direction.x;
+                let t_principal = (r_in.origin.x - r_out.origin.x) / r_out.direction.x;
+                (r_out.at(t_principal).z, r_out.at(t_focal).z)
+            }
+            None => (f64::NAN, f64::NAN),
+        };
+
+        let from_scene = Ray::new(
+            Vec3::new(x, 0.0, -self.front_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let from_film = Ray::new(
+            Vec3::new(x, 0.0, 1.0 - self.rear_z()),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let scene_side = cardinal(&from_scene, self.trace_from_scene(from_scene));
+        let film_side = cardinal(&from_film, self.trace_from_film(from_film).map(|(r, _)| r));
+        (scene_side, film_side)
+    }
+
+    /// How far to move the rear element away from the film to focus at
+    /// `focus_distance`, treating the system as a thick lens.
+    fn thick_lens_focus_delta(&self, focus_distance: f64) -> f64 {
+        let ((p_image, f_image), (p_object, _)) = self.cardinal_points();
+        let f = f_image - p_image;
+        let z = -focus_distance;
+        let c = (p_object - z - p_image) * (p_object - z - 4.0 * f - p_image);
+        0.5 * (p_object - z + p_image - c.sqrt())
+    }
+
+    /// Bound the region of the rear element through which light reaches film points
+    /// between radius `r0` and `r1` along the x axis.
+    fn bound_exit_pupil(&self, r0: f64, r1: f64) -> Bounds {
+        const GRID: usize = 128;
+        let rear_radius = self.elements.last().map_or(0.0, |e| e.aperture_radius);
+        let extent = 1.5 * rear_radius;
+        let mut bounds = Bounds::EMPTY;
+
+        for i in 0..GRID * GRID {
+            // Regular grid over the rear element, sweeping the film point along [r0, r1].
+            let px = -extent + 2.0 * extent * ((i % GRID) as f64 + 0.5) / GRID as f64;
+            let py = -extent + 2.0 * extent * ((i / GRID) as f64 + 0.5) / GRID as f64;
+            let film_x = r0 + (r1 - r0) * (i as f64 + 0.5) / (GRID * GRID) as f64;
+
+            let film = Vec3::new(film_x, 0.0, 0.0);
+            let rear = Vec3::new(px, py, -self.rear_z());
+            if self.trace_from_film(Ray::new(film, rear - film)).is_some() {
+                bounds.include(px, py);
+            }
+        }
+
+        // Grow by a grid cell so the sampled bounds don't clip the true pupil.
+        let cell = 2.0 * extent / GRID as f64;
+        if bounds.area() > 0.0 {
+            bounds.min_x -= cell;
+            bounds.min_y -= cell;
+            bounds.max_x += cell;
+            bounds.max_y += cell;
+        }
+        bounds
+    }
+}
+
+fn air_if_zero(eta: f64) -> f64 {
+    if eta == 0.0 { 1.0 } else { eta }
+}
+
+/// Intersect a ray with a spherical surface centred on the axis at
+/// `element_z + radius`. Returns the hit distance and the unit normal facing the ray.
+fn intersect_spherical(radius: f64, element_z: f64, r: &Ray) -> Option<(f64, Vec3)> {
+    let z_center = element_z + radius;
+    let o = r.origin - Vec3::new(0.0, 0.0, z_center);
+    let a = r.direction.length_squared();
+    let b = 2.0 * Vec3::dot(r.direction, o);
+    let c = o.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-b - sqrtd) / (2.0 * a);
+    let t1 = (-b + sqrtd) / (2.0 * a);
+
+    // Which of the two roots lies on the lens surface depends on the travel direction
+    // and which way the surface bulges.
+    let use_closer = (r.direction.z > 0.0) != (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = Vec3::unit_vector(o + t * r.direction);
+    if Vec3::dot(normal, r.direction) > 0.0 {
+        Some((t, -normal))
+    } else {
+        Some((t, normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lens_system::LensSystem, ray::Ray, vec3::Vec3};
+
+    const DOUBLE_GAUSS: &str = include_str!("../lenses/dgauss.50mm.dat");
+
+    #[test]
+    fn test_focal_length() {
+        let lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        let (principal_plane, focal_point) = lens.cardinal_points().0;
+        assert!((focal_point - principal_plane - 50.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_focus_at_infinity_puts_film_at_focal_point() {
+        let mut lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        lens.focus(1e12, 35.0);
+        let (_, focal_point) = lens.cardinal_points().0;
+        assert!(focal_point.abs() < 0.01);
+
+        // Refocusing starts from the prescription, so it ends up in the same place.
+        lens.focus(1000.0, 35.0);
+        lens.focus(1e12, 35.0);
+        let (_, refocused) = lens.cardinal_points().0;
+        assert_eq!(focal_point, refocused);
+    }
+
+    #[test]
+    fn test_exit_pupil_bounds_hold_every_ray_through_the_lens() {
+        let mut lens = LensSystem::parse(DOUBLE_GAUSS).unwrap();
+        lens.focus(1000.0, 35.0);
+        let bands = lens.exit_pupil.len();
+
+        // On axis the pupil is a centred disc; at the corner vignetting has narrowed it.
+        let centre = lens.exit_pupil[0];
+        assert!(centre.area() > 0.0);
+        assert!((centre.min_y + centre.max_y).abs() < 1e-9);
+        assert!(lens.exit_pupil[bands - 1].area() < centre.area());
+
+        // Rays that get through from the middle of a band pass inside its bounds,
+        // probed on a grid offset from the one the bounds were made with.
+        const GRID: usize = 101;
+        let extent = 1.5 * lens.elements.last().unwrap().aperture_radius;
+        for (band, bounds) in lens.exit_pupil.iter().enumerate().step_by(8) {
+            let film = Vec3::new(
+                (band as f64 + 0.5) / bands as f64 * lens.film_radius,
+                0.0,
+                0.0,
+            );
+            for i in 0..GRID * GRID {
+                let px = extent * (2.0 * (i % GRID) as f64 / (GRID - 1) as f64 - 1.0);
+                let py = extent * (2.0 * (i / GRID) as f64 / (GRID - 1) as f64 - 1.0);
+                let rear = Vec3::new(px, py, -lens.rear_z());
+                if lens.trace_from_film(Ray::new(film, rear - film)).is_some() {
+                    assert!(
+                        bounds.min_x <= px && px <= bounds.max_x,
+                        "band {band} x {px}"
+                    );
+                    assert!(
+                        bounds.min_y <= py && py <= bounds.max_y,
+                        "band {band} y {py}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::{fs, io, path::Path, str::FromStr};
+
+use crate::{color::Color, vec3::Vec3};
+
+/// How a 3D LUT is read between its lattice points.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Blend the eight corners of the enclosing cube.
+    #[default]
+    Trilinear,
+    /// Blend the four corners of the tetrahedron of the cube holding the colour,
+    /// which keeps the grey axis exact and avoids hue shifts between points.
+    Tetrahedral,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Interpolation, String> {
+        match s {
+            "trilinear" => Ok(Interpolation::Trilinear),
+            "tetrahedral" => Ok(Interpolation::Tetrahedral),
+            _ => Err(format!("Unknown interpolation: {s}")),
+        }
+    }
+}
+
+/// Evenly spaced samples of a colour transform over an input domain: one curve
+/// per channel for a 1D table, or a lattice with red varying fastest for a 3D one.
+#[derive(Clone, Debug, PartialEq)]
+struct Table {
+    size: usize,
+    entries: Vec<Vec3>,
+    domain_min: Vec3,
+    domain_max: Vec3,
+}
+
+impl Table {
+    /// The position of the colour in table steps along each axis, clamped to the
+    /// domain.
+    fn position(&self, c: Vec3) -> Vec3 {
+        let last = (self.size - 1) as f64;
+        let axis = |x: f64, min: f64, max: f64| ((x - min) / (max - min)).clamp(0.0, 1.0) * last;
+        Vec3::new(
+            axis(c.x, self.domain_min.x, self.domain_max.x),
+            axis(c.y, self.domain_min.y, self.domain_max.y),
+            axis(c.z, self.domain_min.z, self.domain_max.z),
+        )
+    }
+
+    fn apply_1d(&self, c: Vec3) -> Vec3 {
+        let p = self.position(c);
+        let curve = |t: f64, channel: fn(Vec3) -> f64| {
+            let i = (t as usize).min(self.size - 2);
+            let f = t - i as f64;
+            (1.0 - f) * channel(self.entries[i]) + f * channel(self.entries[i + 1])
+        };
+        Vec3::new(
+            curve(p.x, |v| v.x),
+            curve(p.y, |v| v.y),
+            curve(p.z, |v| v.z),
+        )
+    }
+
+    fn apply_3d(&self, c: Vec3, interpolation: Interpolation) -> Vec3 {
+        let p = self.position(c);
+        let base = |t: f64| (t as usize).min(self.size - 2);
+        let (r, g, b) = (base(p.x), base(p.y), base(p.z));
+        let (fr, fg, fb) = (p.x - r as f64, p.y - g as f64, p.z - b as f64);
+        // The lattice point offset from the base corner by 0 or 1 along each axis.
+        let at = |dr: usize, dg: usize, db: usize| {
+            self.entries[((b + db) * self.size + g + dg) * self.size + r + dr]
+        };
+
+        match interpolation {
+            Interpolation::Trilinear => {
+                let lerp = |a: Vec3, b: Vec3, t: f64| (1.0 - t) * a + t * b;
+                let c00 = lerp(at(0, 0, 0), at(1, 0, 0), fr);
+                let c10 = lerp(at(0, 1, 0), at(1, 1, 0), fr);
+                let c01 = lerp(at(0, 0, 1), at(1, 0, 1), fr);
+                let c11 = lerp(at(0, 1, 1), at(1, 1, 1), fr);
+                lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+            }
+            Interpolation::Tetrahedral => {
+                // Walk from the base corner to the far corner along the axes in
+                // order of decreasing fraction.
+                let mut axes = [(fr, (1, 0, 0)), (fg, (0, 1, 0)), (fb, (0, 0, 1))];
+                axes.sort_by(|a, b| b.0.total_cmp(&a.0));
+                let mut corner = (0, 0, 0);
+                let mut result = (1.0 - axes[0].0) * at(0, 0, 0);
+                for (i, (fraction, step)) in axes.iter().enumerate() {
+                    corner = (corner.0 + step.0, corner.1 + step.1, corner.2 + step.2);
+                    let next_fraction = axes.get(i + 1).map_or(0.0, |a| a.0);
+                    result += (fraction - next_fraction) * at(corner.0, corner.1, corner.2);
+                }
+                result
+            }
+        }
+    }
+}
+
+/// The largest table sizes accepted, the limits of the `.cube` specification. A
+/// 3D table of the largest size has over 16 million entries.
+const MAX_1D_SIZE: usize = 65536;
+const MAX_3D_SIZE: usize = 256;
+
+/// A colour grading look from a `.cube` file: a 1D table, a 3D table, or a 1D
+/// shaper followed by a 3D table as Resolve writes them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    shaper: Option<Table>,
+    cube: Option<Table>,
+    pub interpolation: Interpolation,
+}
+
+impl Lut {
+    /// Read an Adobe or Resolve `.cube` file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Lut> {
+        Lut::parse(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Lut, String> {
+        let mut size_1d = None;
+        let mut size_3d = None;
+        let mut domain = (Vec3::zero(), Vec3::new(1.0, 1.0, 1.0));
+        let (mut range_1d, mut range_3d) = (None, None);
+        let mut entries = Vec::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let numbers = || {
+                line.split_whitespace()
+                    .skip(1)
+                    .map(|v| v.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("Bad LUT line: {line}"))
+            };
+            let size = |max: usize| {
+                words
+                    .clone()
+                    .next()
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .filter(|n| (2..=max).contains(n))
+                    .ok_or(format!("Bad LUT size: {line}"))
+            };
+            let triple = |values: Vec<f64>| match values[..] {
+                [r, g, b] => Ok(Vec3::new(r, g, b)),
+                _ => Err(format!("Expected 3 values: {line}")),
+            };
+            let range = |values: Vec<f64>| match values[..] {
+                [min, max] if min < max => Ok((Vec3::new(min, min, min), Vec3::new(max, max, max))),
+                _ => Err(format!("Bad input range: {line}")),
+            };
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => size_1d = Some(size(MAX_1D_SIZE)?),
+                "LUT_3D_SIZE" => size_3d = Some(size(MAX_3D_SIZE)?),
+                "DOMAIN_MIN" => domain.0 = triple(numbers()?)?,
+                "DOMAIN_MAX" => domain.1 = triple(numbers()?)?,
+                "LUT_1D_INPUT_RANGE" => range_1d = Some(range(numbers()?)?),
+                "LUT_3D_INPUT_RANGE" => range_3d = Some(range(numbers()?)?),
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    return Err(format!("Unknown LUT keyword: {keyword}"));
+                }
+                _ => {
+                    let values = line
+                        .split_whitespace()
+                        .map(|v| v.parse::<f64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| format!("Bad LUT entry: {line}"))?;
+                    entries.push(triple(values)?);
+                }
+            }
+        }
+
+        let (min, max) = domain;
+        if !(min.x < max.x && min.y < max.y && min.z < max.z) {
+            return Err(String::from("LUT DOMAIN_MIN is not below DOMAIN_MAX"));
+        }
+
+        let count_1d = size_1d.unwrap_or(0);
+        let count_3d = size_3d.map_or(0, |n| n * n * n);
+        if count_1d + count_3d == 0 {
+            return Err(String::from("LUT has no LUT_1D_SIZE or LUT_3D_SIZE"));
+        }
+        if entries.len() != count_1d + count_3d {
+            return Err(format!(
+                "LUT has {} entries instead of {}",
+                entries.len(),
+                count_1d + count_3d
+            ));
+        }
+        let table = |size, entries: &[Vec3], range: Option<(Vec3, Vec3)>| {
+            let (domain_min, domain_max) = range.unwrap_or(domain);
+            Table {
+                size,
+                entries: entries.to_vec(),
+                domain_min,
+                domain_max,
+            }
+        };
+        Ok(Lut {
+            shaper: size_1d.map(|n| table(n, &entries[..count_1d], range_1d)),
+            cube: size_3d.map(|n| table(n, &entries[count_1d..], range_3d)),
+            interpolation: Interpolation::default(),
+        })
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let mut c = Vec3::from(color);
+        if let Some(shaper) = &self.shaper {
+            c = shaper.apply_1d(c);
+        }
+        if let Some(cube) = &self.cube {
+            c = cube.apply_3d(c, self.interpolation);
+        }
+        c.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        lut::{Interpolation, Lut},
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_identity_cube_and_shaper() {
+        // A 3D identity with a shaper that squares each channel.
+        let mut text = String::from("TITLE \"test\"\nLUT_1D_SIZE 3\nLUT_3D_SIZE 2\n");
+        text += "0 0 0\n0.25 0.25 0.25\n1 1 1\n";
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    text += &format!("{r} {g} {b}\n");
+                }
+            }
+        }
+        let mut lut = Lut::parse(&text).unwrap();
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tetrahedral] {
+            lut.interpolation = interpolation;
+            let c = Vec3::from(lut.apply(Color::new(0.5, 0.25, 0.75)));
+            assert!((c - Vec3::new(0.25, 0.125, 0.625)).length() < 1e-12);
+        }
+
+        assert!(Lut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut::parse("LUT_3D_SIZE 257\n").is_err());
+        assert!(Lut::parse("LUT_3D_SIZE 3000000\n0 0 0\n").is_err());
+
+        // An empty or inverted domain in any channel would divide by zero or flip it.
+        let identity = text.replace("TITLE \"test\"\n", "");
+        assert!(Lut::parse(&format!("DOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 1 2\n{identity}")).is_ok());
+        assert!(Lut::parse(&format!("DOMAIN_MIN 0 1 0\nDOMAIN_MAX 1 1 1\n{identity}")).is_err());
+        assert!(Lut::parse(&format!("DOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 1 -1\n{identity}")).is_err());
+    }
+}
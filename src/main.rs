@@ -1,125 +1,158 @@
+mod alpha_mask;
+mod aov;
+mod aperture;
+mod blue_noise;
 mod camera;
+mod cli;
 mod color;
+mod colorspace;
+mod denoise;
+mod distributed;
+mod dither;
+mod exr;
+mod film;
+mod filter;
 mod hittable;
 mod hittable_list;
+mod image;
 mod interval;
+mod lens_system;
+mod lut;
 mod material;
+mod netpbm;
+mod png;
+mod post;
+mod quad;
 mod ray;
+mod sampler;
+mod scenes;
 mod sphere;
+mod texture;
+mod tonemap;
 mod util;
 mod vec3;
 
-use hittable_list::HittableList;
-use sphere::Sphere;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::TcpListener,
+    process,
+};
 
 use crate::{
     camera::Camera,
-    color::Color,
-    material::{Dielectric, Lambertian, Metal},
-    util::{random_f64, random_f64_range},
-    vec3::{Point3, Vec3},
+    cli::{Args, USAGE},
+    hittable_list::HittableList,
+    netpbm::write_netpbm,
 };
 
 fn main() {
-    let mut world = HittableList::new();
-
-    let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
-    world.add(Sphere::new(
-        Point3::new(0., -1000., 0.),
-        1000.,
-        ground_material,
-    ));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64();
-            let center = Point3::new(
-                a as f64 + 0.9 * random_f64(),
-                0.2,
-                b as f64 + 0.9 * random_f64(),
-            );
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Vec3::random() * Vec3::random();
-                    let sphere_material = Lambertian::new(albedo.into());
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Vec3::random_range(0.5, 1.0).into();
-                    let fuzz = random_f64_range(0.0, 0.5);
-                    let sphere_material = Metal::new(albedo, fuzz);
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                } else {
-                    // glass
-                    let sphere_material = Dielectric::new(1.5);
-                    world.add(Sphere::new(center, 0.2, sphere_material));
-                }
-            }
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        if !e.is_empty() {
+            eprintln!("{e}");
         }
+        eprintln!("{USAGE}");
+        process::exit(2);
+    });
+
+    if let Some(address) = &args.worker {
+        distributed::work(address, |job| setup(&Args::parse(job.into_iter())?)).unwrap_or_else(
+            |e| {
+                eprintln!("Worker stopped: {e}");
+                process::exit(1);
+            },
+        );
+        return;
     }
 
-    let material1 = Dielectric::new(1.5);
-    world.add(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material1));
-
-    let material2 = Lambertian::new(Color::new(0.4, 0.2, 0.1));
-    world.add(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material2));
-
-    let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
-    world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material3));
-
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = 1200;
-    cam.samples_per_pixel = 500;
-    cam.max_depth = 50;
+    let (world, mut cam) = setup(&args).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+
+    if let Some(address) = &args.coordinator {
+        let listener = TcpListener::bind(address).unwrap_or_else(|e| {
+            eprintln!("Cannot listen on {address}: {e}");
+            process::exit(1);
+        });
+        distributed::coordinate(
+            &mut cam,
+            listener,
+            &args.raw,
+            args.tile_rows,
+            args.worker_timeout,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Cannot send input files to workers: {e}");
+            process::exit(1);
+        });
+    } else {
+        cam.render(&world);
+    }
 
-    cam.vfov = 20.;
-    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
-    cam.lookat = Point3::new(0.0, 0.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    let (image, alpha) = (cam.image(), cam.alpha());
+    let written = match &args.output {
+        Some(path) => File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_netpbm(&mut out, &image, alpha.as_ref(), &cam.display, cam.format)?;
+            out.flush()
+        }),
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            write_netpbm(&mut out, &image, alpha.as_ref(), &cam.display, cam.format)
+                .and_then(|()| out.flush())
+        }
+    };
+    written.unwrap_or_else(|e| {
+        let path = args.output.as_deref().unwrap_or("standard output");
+        eprintln!("Cannot write the image to {path}: {e}");
+        process::exit(1);
+    });
+
+    if let Some(path) = &args.png {
+        let written = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            png::write_png(&mut out, &image, alpha.as_ref(), &cam.display)?;
+            out.flush()
+        });
+        written.unwrap_or_else(|e| {
+            eprintln!("Cannot write {path}: {e}");
+            process::exit(1);
+        });
+    }
 
-    cam.defocus_angle = 0.6;
-    cam.focus_dist = 10.0;
+    if let Some(path) = &args.aov_file {
+        let written = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            let chromaticities = cam.display.working_space.chromaticities();
+            exr::write_exr(&mut out, &cam.layers(), &chromaticities)?;
+            out.flush()
+        });
+        written.unwrap_or_else(|e| {
+            eprintln!("Cannot write AOVs to {path}: {e}");
+            process::exit(1);
+        });
+    }
 
-    cam.render(&world);
+    if let Some(path) = &args.sample_map {
+        cam.sample_counts().save_ppm(path).unwrap_or_else(|e| {
+            eprintln!("Cannot write sample map {path}: {e}");
+            process::exit(1);
+        });
+    }
 }
 
-fn _old_main() {
-    let mut world = HittableList::new();
-
-    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.0));
-    let material_center = Lambertian::new(Color::new(0.1, 0.2, 0.5));
-    let material_left = Dielectric::new(1.50);
-    let material_bubble = Dielectric::new(1.00 / 1.50);
-    let material_right = Metal::new(Color::new(0.8, 0.6, 0.2), 1.0);
-
-    world.add(Sphere::new(
-        Point3::new(0., -100.5, -1.),
-        100.,
-        material_ground,
-    ));
-    world.add(Sphere::new(Point3::new(0., 0., -1.2), 0.5, material_center));
-    world.add(Sphere::new(Point3::new(-1.0, 0., -1.0), 0.5, material_left));
-    world.add(Sphere::new(
-        Point3::new(-1.0, 0., -1.0),
-        0.4,
-        material_bubble,
-    ));
-    world.add(Sphere::new(Point3::new(1.0, 0., -1.0), 0.5, material_right));
-
-    let mut cam = Camera::new();
-    cam.aspect_ratio = 16.0 / 9.0;
-    cam.image_width = 400;
-    cam.samples_per_pixel = 100;
-    cam.max_depth = 50;
-    cam.vfov = 20.;
-    cam.lookfrom = Point3::new(-2.0, 2.0, 1.0);
-    cam.lookat = Point3::new(0.0, 0.0, -1.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-
-    cam.defocus_angle = 10.0;
-    cam.focus_dist = 3.4;
-
-    cam.render(&world);
+/// Build the scene named on the command line and apply the camera overrides.
+fn setup(args: &Args) -> Result<(HittableList<'static>, Camera), String> {
+    let (world, mut cam) = match args.scene.as_str() {
+        "final" => scenes::final_scene(args.scene_seed),
+        "three-spheres" => scenes::three_spheres(),
+        "layered-materials" => scenes::layered_materials(),
+        "mapped-spheres" => scenes::mapped_spheres(args.normal_map.as_deref())?,
+        "cutout-fence" => scenes::cutout_fence(),
+        "light-groups" => scenes::light_groups(),
+        other => return Err(format!("Unknown scene: {other}\n{USAGE}")),
+    };
+    args.configure(&mut cam)?;
+    Ok((world, cam))
 }
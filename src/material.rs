@@ -1,7 +1,47 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, util::random_f64, vec3::Vec3};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec3::Vec3,
+};
 
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    /// Every random choice draws on `sampler`, in the same order for every call.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)>;
+
+    /// Scatter light that a coating such as `ThinFilm` has already let through the
+    /// surface, so the interface reflection is not counted again. The same as
+    /// `scatter` unless the material reflects at its interface.
+    fn transmit(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        self.scatter(r_in, rec, sampler)
+    }
+
+    /// Light given off at the hit.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// The camera's light group that `emitted` light is counted in.
+    fn light_group(&self) -> usize {
+        0
+    }
+
+    /// The overall reflectance at the hit, for the albedo AOV.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
 
 pub struct Lambertian {
@@ -10,13 +50,18 @@ pub struct Lambertian {
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
-        Lambertian { albedo: albedo }
+        Lambertian { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let mut scatter_direction = rec.normal + Vec3::sample_unit_sphere(sampler.get_2d());
 
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
@@ -26,6 +71,10 @@ impl Material for Lambertian {
         let attenuation = self.albedo.clone();
         Some((attenuation, scattered))
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo.clone()
+    }
 }
 
 pub struct Metal {
@@ -36,26 +85,36 @@ pub struct Metal {
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Metal {
         Metal {
-            albedo: albedo,
+            albedo,
             fuzz: if fuzz < 1. { fuzz } else { 1. },
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
         let mut reflected = Vec3::reflect(r_in.direction, rec.normal);
-        reflected = Vec3::unit_vector(reflected) + (self.fuzz * Vec3::random_unit_vector());
+        reflected =
+            Vec3::unit_vector(reflected) + (self.fuzz * Vec3::sample_unit_sphere(sampler.get_2d()));
 
         let scattered = Ray::new(rec.p, reflected);
         let attenuation = self.albedo.clone();
 
-        if Vec3::dot(scattered.direction, rec.normal) > 0. {
+        if Vec3::dot(scattered.direction, rec.geometric_normal) > 0. {
             Some((attenuation, scattered))
         } else {
             None
         }
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo.clone()
+    }
 }
 
 pub struct Dielectric {
@@ -64,20 +123,18 @@ pub struct Dielectric {
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Dielectric {
-        Dielectric {
-            refraction_index: refraction_index,
-        }
+        Dielectric { refraction_index }
     }
-    fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
+    pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
         let mut r0 = (1. - refraction_index) / (1. + refraction_index);
         r0 = r0 * r0;
         r0 + (1. - r0) * ((1. - cosine).powf(5.0))
     }
-}
 
-impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
+    /// Refract through the surface, or reflect where total internal reflection
+    /// forces it or `reflect` chooses to, given the cosine of the angle of
+    /// incidence and the relative index of refraction.
+    fn bend(&self, r_in: &Ray, rec: &HitRecord, reflect: impl FnOnce(f64, f64) -> bool) -> Ray {
         let ri = if rec.front_face {
             1.0 / self.refraction_index
         } else {
@@ -91,15 +148,514 @@ impl Material for Dielectric {
 
         let cannot_refract = ri * sin_theta > 1.0;
 
-        let direction;
-        if cannot_refract || Dielectric::reflectance(cos_theta, ri) > random_f64() {
-            direction = Vec3::reflect(unit_direction, rec.normal);
+        let direction = if cannot_refract || reflect(cos_theta, ri) {
+            Vec3::reflect(unit_direction, rec.normal)
         } else {
-            direction = Vec3::refract(unit_direction, rec.normal, ri);
-        }
+            Vec3::refract(unit_direction, rec.normal, ri)
+        };
 
-        let scattered = Ray::new(rec.p, direction);
+        Ray::new(rec.p, direction)
+    }
+}
 
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let attenuation = Color::new(1.0, 1.0, 1.0);
+        let scattered = self.bend(r_in, rec, |cos_theta, ri| {
+            Dielectric::reflectance(cos_theta, ri) > sampler.get_1d()
+        });
         Some((attenuation, scattered))
     }
+
+    fn transmit(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let attenuation = Color::new(1.0, 1.0, 1.0);
+        Some((attenuation, self.bend(r_in, rec, |_, _| false)))
+    }
+}
+
+/// Wavelengths in nanometres used to evaluate the red, green and blue channels
+/// of wavelength-dependent effects.
+const RGB_WAVELENGTHS: [f64; 3] = [650.0, 510.0, 475.0];
+
+/// A thin transparent film (soap, oil) layered over another material.
+///
+/// Light reflected off the top and bottom of the film interferes, so the reflected
+/// colour depends on the film thickness, its index of refraction and the viewing angle.
+/// Whatever is not reflected by the film is transmitted into the base material.
+pub struct ThinFilm<'a> {
+    base: Box<dyn Material + 'a>,
+    thickness: f64, // nanometres
+    film_index: f64,
+    substrate_index: f64,
+}
+
+impl<'a> ThinFilm<'a> {
+    /// Indices of refraction are relative to the medium the surface normal points into,
+    /// the same convention `Dielectric` uses.
+    pub fn new(
+        base: impl Material + 'a,
+        thickness: f64,
+        film_index: f64,
+        substrate_index: f64,
+    ) -> ThinFilm<'a> {
+        ThinFilm {
+            base: Box::new(base),
+            thickness,
+            film_index,
+            substrate_index,
+        }
+    }
+
+    /// Airy reflectance of the film for unpolarised light of the given wavelength.
+    fn reflectance(&self, cos_i: f64, n1: f64, n3: f64, wavelength: f64) -> f64 {
+        let n2 = self.film_index;
+        let sin2_i = 1.0 - cos_i * cos_i;
+
+        let sin2_film = (n1 / n2) * (n1 / n2) * sin2_i;
+        let sin2_sub = (n1 / n3) * (n1 / n3) * sin2_i;
+        if sin2_film >= 1.0 || sin2_sub >= 1.0 {
+            return 1.0; // total internal reflection
+        }
+        let cos_film = (1.0 - sin2_film).sqrt();
+        let cos_sub = (1.0 - sin2_sub).sqrt();
+
+        let phase = 4.0 * std::f64::consts::PI * n2 * self.thickness * cos_film / wavelength;
+        let airy = |r12: f64, r23: f64| {
+            let interference = 2.0 * r12 * r23 * phase.cos();
+            (r12 * r12 + r23 * r23 + interference) / (1.0 + r12 * r12 * r23 * r23 + interference)
+        };
+
+        let rs = airy(
+            (n1 * cos_i - n2 * cos_film) / (n1 * cos_i + n2 * cos_film),
+            (n2 * cos_film - n3 * cos_sub) / (n2 * cos_film + n3 * cos_sub),
+        );
+        let rp = airy(
+            (n2 * cos_i - n1 * cos_film) / (n2 * cos_i + n1 * cos_film),
+            (n3 * cos_film - n2 * cos_sub) / (n3 * cos_film + n2 * cos_sub),
+        );
+        0.5 * (rs + rp)
+    }
+}
+
+impl<'a> Material for ThinFilm<'a> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let (n1, n3) = if rec.front_face {
+            (1.0, self.substrate_index)
+        } else {
+            (self.substrate_index, 1.0)
+        };
+
+        let unit_direction = Vec3::unit_vector(r_in.direction);
+        let cos_i = Vec3::dot(-unit_direction, rec.normal).clamp(0.0, 1.0);
+        let [r, g, b] = RGB_WAVELENGTHS.map(|lambda| self.reflectance(cos_i, n1, n3, lambda));
+
+        // Pick reflection or the base material in proportion to the average film
+        // reflectance, and weight the result so the estimate stays unbiased.
+        let p_reflect = (r + g + b) / 3.0;
+        if sampler.get_1d() < p_reflect {
+            let reflected = Vec3::reflect(unit_direction, rec.normal);
+            let attenuation = Color::new(r, g, b);
+            return Some(((1.0 / p_reflect) * attenuation, Ray::new(rec.p, reflected)));
+        }
+
+        let (attenuation, scattered) = self.base.transmit(r_in, rec, sampler)?;
+        let transmitted = Color::new(1.0 - r, 1.0 - g, 1.0 - b);
+        Some((
+            (1.0 / (1.0 - p_reflect)) * transmitted * attenuation,
+            scattered,
+        ))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// Blends two materials: at each hit the second material is chosen with probability
+/// equal to the weight (the luminance of the weight texture), the first otherwise.
+pub struct MixMaterial<'a> {
+    first: Box<dyn Material + 'a>,
+    second: Box<dyn Material + 'a>,
+    weight: Box<dyn Texture + 'a>,
+}
+
+impl<'a> MixMaterial<'a> {
+    pub fn new(
+        first: impl Material + 'a,
+        second: impl Material + 'a,
+        weight: f64,
+    ) -> MixMaterial<'a> {
+        MixMaterial::textured(
+            first,
+            second,
+            SolidColor::new(Color::new(weight, weight, weight)),
+        )
+    }
+    pub fn textured(
+        first: impl Material + 'a,
+        second: impl Material + 'a,
+        weight: impl Texture + 'a,
+    ) -> MixMaterial<'a> {
+        MixMaterial {
+            first: Box::new(first),
+            second: Box::new(second),
+            weight: Box::new(weight),
+        }
+    }
+}
+
+impl<'a> Material for MixMaterial<'a> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let weight = self.weight.value(rec.u, rec.v, rec.p).luminance();
+        if sampler.get_1d() < weight {
+            self.second.scatter(r_in, rec, sampler)
+        } else {
+            self.first.scatter(r_in, rec, sampler)
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let weight = self.weight.value(rec.u, rec.v, rec.p).luminance();
+        (1.0 - weight) * self.first.albedo(rec) + weight * self.second.albedo(rec)
+    }
+}
+
+/// A clear dielectric coat (lacquer, varnish) over a base material.
+///
+/// The coat reflects specularly with its Fresnel reflectance; the remaining light
+/// scatters off the base.
+pub struct Coated<'a> {
+    base: Box<dyn Material + 'a>,
+    refraction_index: f64,
+}
+
+impl<'a> Coated<'a> {
+    pub fn new(base: impl Material + 'a, refraction_index: f64) -> Coated<'a> {
+        Coated {
+            base: Box::new(base),
+            refraction_index,
+        }
+    }
+}
+
+impl<'a> Material for Coated<'a> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec, sampler);
+        }
+
+        let unit_direction = Vec3::unit_vector(r_in.direction);
+        let cos_theta = Vec3::dot(-unit_direction, rec.normal).min(1.0);
+
+        if Dielectric::reflectance(cos_theta, 1.0 / self.refraction_index) > sampler.get_1d() {
+            let reflected = Vec3::reflect(unit_direction, rec.normal);
+            Some((Color::new(1.0, 1.0, 1.0), Ray::new(rec.p, reflected)))
+        } else {
+            self.base.scatter(r_in, rec, sampler)
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// Perturbs the shading normal with a tangent-space normal map before the base
+/// material scatters. Map colours encode the normal as `0.5 * (n + 1)`.
+pub struct NormalMapped<'a> {
+    base: Box<dyn Material + 'a>,
+    normal_map: Box<dyn Texture + 'a>,
+}
+
+impl<'a> NormalMapped<'a> {
+    pub fn new(base: impl Material + 'a, normal_map: impl Texture + 'a) -> NormalMapped<'a> {
+        NormalMapped {
+            base: Box::new(base),
+            normal_map: Box::new(normal_map),
+        }
+    }
+}
+
+impl<'a> Material for NormalMapped<'a> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        let encoded: Vec3 = self.normal_map.value(rec.u, rec.v, rec.p).into();
+        let n = 2.0 * encoded - Vec3::new(1.0, 1.0, 1.0);
+
+        let perturbed = n.x * rec.tangent + n.y * rec.bitangent() + n.z * rec.normal;
+        self.base
+            .scatter(r_in, &with_shading_normal(rec, perturbed), sampler)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// Perturbs the shading normal by the gradient of a height texture before the
+/// base material scatters. Only the luminance of the height texture is used.
+pub struct BumpMapped<'a> {
+    base: Box<dyn Material + 'a>,
+    height: Box<dyn Texture + 'a>,
+    scale: f64,
+}
+
+impl<'a> BumpMapped<'a> {
+    pub fn new(base: impl Material + 'a, height: impl Texture + 'a, scale: f64) -> BumpMapped<'a> {
+        BumpMapped {
+            base: Box::new(base),
+            height: Box::new(height),
+            scale,
+        }
+    }
+}
+
+impl<'a> Material for BumpMapped<'a> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        const DELTA: f64 = 1.0 / 1024.0;
+        let bitangent = rec.bitangent();
+
+        let h = self.height.value(rec.u, rec.v, rec.p).luminance();
+        let h_u = self
+            .height
+            .value(rec.u + DELTA, rec.v, rec.p + DELTA * rec.tangent)
+            .luminance();
+        let h_v = self
+            .height
+            .value(rec.u, rec.v + DELTA, rec.p + DELTA * bitangent)
+            .luminance();
+
+        let dh_du = (h_u - h) / DELTA;
+        let dh_dv = (h_v - h) / DELTA;
+        let perturbed = rec.normal - self.scale * (dh_du * rec.tangent + dh_dv * bitangent);
+        self.base
+            .scatter(r_in, &with_shading_normal(rec, perturbed), sampler)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// An emitter that absorbs everything that hits it. It glows only on the front side,
+/// and its light is counted in one of the camera's light groups.
+pub struct DiffuseLight {
+    emit: Color,
+    light_group: usize,
+}
+
+impl DiffuseLight {
+    /// `light_group` indexes the camera's `light_groups`.
+    pub fn new(emit: Color, light_group: usize) -> DiffuseLight {
+        DiffuseLight { emit, light_group }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit.clone()
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn light_group(&self) -> usize {
+        self.light_group
+    }
+}
+
+/// Copy of `rec` with its shading normal replaced, kept on the same side as the
+/// geometric normal so the perturbation never flips the surface.
+fn with_shading_normal<'a>(rec: &HitRecord<'a>, normal: Vec3) -> HitRecord<'a> {
+    let mut perturbed = rec.clone();
+    perturbed.normal = Vec3::unit_vector(normal);
+    if Vec3::dot(perturbed.normal, rec.geometric_normal) <= 0.0 {
+        perturbed.normal = rec.geometric_normal;
+    }
+    perturbed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        hittable::HitRecord,
+        material::{
+            BumpMapped, Coated, Dielectric, Lambertian, Material, Metal, MixMaterial, NormalMapped,
+            ThinFilm,
+        },
+        ray::Ray,
+        sampler::SamplerKind,
+        texture::SolidColor,
+        vec3::{Point3, Vec3},
+    };
+
+    /// A hit at the origin on the plane z = 0, facing +z with its tangent along x.
+    fn hit_at_origin(mat: &dyn Material) -> HitRecord<'_> {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let tangent = Vec3::new(1.0, 0.0, 0.0);
+        HitRecord::new(Point3::new(0.0, 0.0, 0.0), normal, tangent, 1.0, mat)
+    }
+
+    /// The mean luminance of the attenuation of many scatters of a ray arriving along
+    /// `direction`, checking that no single one gains energy.
+    fn mean_attenuation(mat: &dyn Material, direction: Vec3) -> f64 {
+        let rec = hit_at_origin(mat);
+        let r_in = Ray::new(Point3::new(0.0, 0.0, 0.0) - direction, direction);
+        let mut sampler = SamplerKind::Independent.create(4096, 0);
+        let mut sum = 0.0;
+        for i in 0..4096 {
+            sampler.start_pixel_sample(0, 0, i);
+            if let Some((attenuation, _)) = mat.scatter(&r_in, &rec, sampler.as_mut()) {
+                let a = attenuation.luminance();
+                assert!(a <= 1.0 + 1e-12, "{a}");
+                sum += a;
+            }
+        }
+        sum / 4096.0
+    }
+
+    #[test]
+    fn test_layered_materials_conserve_energy() {
+        let white = || Lambertian::new(Color::new(1.0, 1.0, 1.0));
+        let mirror = || Metal::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let red = || Lambertian::new(Color::new(0.8, 0.1, 0.1));
+        let layered: [Box<dyn Material>; 4] = [
+            Box::new(MixMaterial::new(white(), mirror(), 0.3)),
+            Box::new(MixMaterial::new(red(), white(), 0.5)),
+            Box::new(Coated::new(white(), 1.5)),
+            Box::new(Coated::new(red(), 1.5)),
+        ];
+        for direction in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(3.0, 0.0, -1.0)] {
+            for mat in &layered {
+                assert!(mean_attenuation(mat.as_ref(), direction) <= 1.0 + 1e-12);
+            }
+        }
+
+        // A clear coat adds its reflection to what the base scatters.
+        let oblique = Vec3::new(3.0, 0.0, -1.0);
+        let base = Color::new(0.8, 0.1, 0.1).luminance();
+        assert!(mean_attenuation(layered[3].as_ref(), oblique) > base + 0.05);
+    }
+
+    #[test]
+    fn test_flat_normal_and_bump_maps_leave_the_normal_alone() {
+        // A mirror shows the shading normal in the direction it reflects.
+        let mirror = || Metal::new(Color::new(1.0, 1.0, 1.0), 0.0);
+        let reflect = |mat: &dyn Material| {
+            let rec = hit_at_origin(mat);
+            let r_in = Ray::new(Point3::new(-1.0, -2.0, 1.0), Vec3::new(1.0, 2.0, -1.0));
+            let mut sampler = SamplerKind::Independent.create(1, 0);
+            sampler.start_pixel_sample(0, 0, 0);
+            Vec3::unit_vector(
+                mat.scatter(&r_in, &rec, sampler.as_mut())
+                    .unwrap()
+                    .1
+                    .direction,
+            )
+        };
+        let plain = reflect(&mirror());
+
+        let flat = SolidColor::new(Color::new(0.5, 0.5, 1.0));
+        let normal_mapped = NormalMapped::new(mirror(), flat);
+        assert!((reflect(&normal_mapped) - plain).length() < 1e-12);
+
+        let level = SolidColor::new(Color::new(0.7, 0.7, 0.7));
+        let bump_mapped = BumpMapped::new(mirror(), level, 5.0);
+        assert!((reflect(&bump_mapped) - plain).length() < 1e-12);
+
+        // A tilted map does move it.
+        let tilted = SolidColor::new(Color::new(0.2, 0.5, 0.9));
+        let tilted_mapped = NormalMapped::new(mirror(), tilted);
+        assert!((reflect(&tilted_mapped) - plain).length() > 0.1);
+    }
+
+    #[test]
+    fn test_thin_film_reflectance() {
+        let (n1, n2, n3) = (1.0, 1.38, 1.5);
+        let lambda = 550.0;
+        let film = |thickness| {
+            ThinFilm::new(
+                Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+                thickness,
+                n2,
+                n3,
+            )
+        };
+
+        // Without a film the substrate's own Fresnel reflectance is left, at any angle.
+        for cos_i in [1.0, 0.7, 0.2] {
+            let cos_t = (1.0 - (n1 / n3) * (n1 / n3) * (1.0 - cos_i * cos_i)).sqrt();
+            let rs = ((n1 * cos_i - n3 * cos_t) / (n1 * cos_i + n3 * cos_t)).powi(2);
+            let rp = ((n3 * cos_i - n1 * cos_t) / (n3 * cos_i + n1 * cos_t)).powi(2);
+            let fresnel = 0.5 * (rs + rp);
+            assert!((film(0.0).reflectance(cos_i, n1, n3, lambda) - fresnel).abs() < 1e-12);
+        }
+
+        // At normal incidence a quarter-wave layer cancels most of the reflection and
+        // a half-wave layer brings it back.
+        let bare = film(0.0).reflectance(1.0, n1, n3, lambda);
+        let quarter = film(lambda / (4.0 * n2)).reflectance(1.0, n1, n3, lambda);
+        let half = film(lambda / (2.0 * n2)).reflectance(1.0, n1, n3, lambda);
+        assert!(quarter < 0.5 * bare, "{quarter} {bare}");
+        assert!((half - bare).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_dielectric_transmit_only_refracts() {
+        let glass = Dielectric::new(1.5);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let rec = hit_at_origin(&glass);
+        let r_in = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        for i in 0..64 {
+            sampler.start_pixel_sample(0, 0, i);
+            let (_, scattered) = glass.transmit(&r_in, &rec, sampler.as_mut()).unwrap();
+            assert!(Vec3::dot(scattered.direction, normal) < 0.0);
+        }
+    }
 }
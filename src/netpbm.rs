@@ -0,0 +1,172 @@
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use crate::{
+    color::{unpremultiply, write_color},
+    colorspace::transform,
+    image::Image,
+    tonemap::DisplayTransform,
+    vec3::Vec3,
+};
+
+/// The Netpbm variants the image can be written as.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum NetpbmFormat {
+    /// P3: plain text, one pixel per line.
+    #[default]
+    Plain,
+    /// P6: binary with 8 bits per channel.
+    Binary,
+    /// P6 with a `maxval` of 65535: binary with 16 big-endian bits per channel.
+    Binary16,
+    /// P5: binary 8-bit greyscale of the luminance of the displayed colour, after
+    /// any grading LUT, weighted for the output space's primaries.
+    Gray,
+    /// P7: binary 8-bit RGB with straight alpha, opaque if there is no alpha image.
+    Pam,
+}
+
+impl FromStr for NetpbmFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<NetpbmFormat, String> {
+        match s {
+            "p3" => Ok(NetpbmFormat::Plain),
+            "p6" => Ok(NetpbmFormat::Binary),
+            "p6-16" => Ok(NetpbmFormat::Binary16),
+            "pgm" => Ok(NetpbmFormat::Gray),
+            "pam" => Ok(NetpbmFormat::Pam),
+            _ => Err(format!("Unknown image format: {s}")),
+        }
+    }
+}
+
+/// Write the image in the given format after passing it through the display
+/// transform. The 8-bit formats are dithered as the transform asks. Only PAM has
+/// an alpha channel; the others show the premultiplied colour over black.
+pub fn write_netpbm(
+    out: &mut impl Write,
+    image: &Image,
+    alpha: Option<&Image>,
+    display: &DisplayTransform,
+    format: NetpbmFormat,
+) -> io::Result<()> {
+    let (width, height) = (image.width(), image.height());
+    match format {
+        NetpbmFormat::Plain | NetpbmFormat::Binary => {
+            let magic = if format == NetpbmFormat::Plain { 3 } else { 6 };
+            writeln!(out, "P{magic}\n{width} {height}\n255")?
+        }
+        NetpbmFormat::Binary16 => writeln!(out, "P6\n{width} {height}\n65535")?,
+        NetpbmFormat::Gray => writeln!(out, "P5\n{width} {height}\n255")?,
+        NetpbmFormat::Pam => write!(
+            out,
+            "P7\nWIDTH {width}\nHEIGHT {height}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n"
+        )?,
+    }
+
+    let space = display.output_space;
+    let to_xyz = space.rgb_to_xyz();
+    for y in 0..height {
+        for x in 0..width {
+            let color = image.pixel(x, y).clone();
+            let byte = |v: f64, channel: usize| display.dither.quantize(v, x, y, channel);
+            match format {
+                NetpbmFormat::Plain => {
+                    write_color(out, display.encode(color), display.dither, x, y)?
+                }
+                NetpbmFormat::Binary => {
+                    let c = Vec3::from(display.encode(color));
+                    out.write_all(&[byte(c.x, 0), byte(c.y, 1), byte(c.z, 2)])?
+                }
+                NetpbmFormat::Binary16 => {
+                    let c = Vec3::from(display.encode(color));
+                    for v in [c.x, c.y, c.z] {
+                        let level = (65535.0 * v.clamp(0.0, 1.0)).round() as u16;
+                        out.write_all(&level.to_be_bytes())?;
+                    }
+                }
+                NetpbmFormat::Gray => {
+                    let c = Vec3::from(display.encode(color));
+                    let linear = Vec3::new(space.decode(c.x), space.decode(c.y), space.decode(c.z));
+                    let luminance = transform(&to_xyz, linear).y.clamp(0.0, 1.0);
+                    out.write_all(&[byte(space.encode(luminance), 1)])?
+                }
+                NetpbmFormat::Pam => {
+                    let a = alpha.map_or(1.0, |alpha| Vec3::from(alpha.pixel(x, y).clone()).x);
+                    let c = Vec3::from(display.encode(unpremultiply(color, a)));
+                    out.write_all(&[byte(c.x, 0), byte(c.y, 1), byte(c.z, 2), byte(a, 3)])?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        image::Image,
+        lut::Lut,
+        netpbm::{NetpbmFormat, write_netpbm},
+        tonemap::DisplayTransform,
+    };
+
+    #[test]
+    fn test_binary_formats() {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Color::new(1.0, 0.0, 1.0));
+        let display = DisplayTransform::default();
+        let written = |format| {
+            let mut out = Vec::new();
+            write_netpbm(&mut out, &image, None, &display, format).unwrap();
+            out
+        };
+
+        assert_eq!(
+            b"P6\n2 1\n255\n\xff\x00\xff\x00\x00\x00",
+            &written(NetpbmFormat::Binary)[..]
+        );
+
+        let wide = written(NetpbmFormat::Binary16);
+        assert_eq!(b"P6\n2 1\n65535\n\xff\xff\x00\x00\xff\xff", &wide[..19]);
+        assert_eq!(13 + 12, wide.len());
+
+        let pam = written(NetpbmFormat::Pam);
+        assert!(pam.ends_with(b"ENDHDR\n\xff\x00\xff\xff\x00\x00\x00\xff"));
+
+        // Straight alpha in PAM: the premultiplied colour is divided by the coverage.
+        let mut alpha = Image::new(2, 1);
+        alpha.set_pixel(0, 0, Color::new(0.5, 0.5, 0.5));
+        image.set_pixel(0, 0, Color::new(0.5, 0.0, 0.25));
+        let mut pam = Vec::new();
+        write_netpbm(&mut pam, &image, Some(&alpha), &display, NetpbmFormat::Pam).unwrap();
+        assert!(pam.ends_with(b"\xff\x00\xbc\x80\x00\x00\x00\x00"));
+    }
+
+    #[test]
+    fn test_gray_goes_through_the_whole_display_pipeline() {
+        let mut image = Image::new(3, 1);
+        image.set_pixel(0, 0, Color::new(1.0, 1.0, 1.0));
+        image.set_pixel(1, 0, Color::new(0.2, 0.2, 0.2));
+        let mut display = DisplayTransform::default();
+        let written = |display: &DisplayTransform, format| {
+            let mut out = Vec::new();
+            write_netpbm(&mut out, &image, None, display, format).unwrap();
+            out
+        };
+
+        // A grey keeps the level each RGB channel gets.
+        let rgb = written(&display, NetpbmFormat::Binary);
+        let gray = written(&display, NetpbmFormat::Gray);
+        assert_eq!(b"P5\n3 1\n255\n\xff", &gray[..12]);
+        assert_eq!(rgb[rgb.len() - 6], gray[12]);
+
+        // A grading LUT that inverts the image applies to PGM too.
+        display.lut = Some(Lut::parse("LUT_1D_SIZE 2\n1 1 1\n0 0 0\n").unwrap());
+        assert_eq!(b"\x00", &written(&display, NetpbmFormat::Gray)[11..12]);
+        assert_eq!(b"\xff", &written(&display, NetpbmFormat::Gray)[13..]);
+    }
+}
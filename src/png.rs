@@ -0,0 +1,219 @@
+use std::io::{self, Write};
+
+use crate::{
+    color::unpremultiply,
+    colorspace::{ColorSpace, D50, bradford, transform},
+    image::Image,
+    tonemap::DisplayTransform,
+    vec3::Vec3,
+};
+
+/// Write the image as an 8-bit RGB PNG after passing it through the display
+/// transform, or as RGBA with straight alpha if there is an alpha image. It is
+/// tagged with its output colour space: an `sRGB` chunk for sRGB and an embedded
+/// ICC profile otherwise, with `cHRM` primaries for decoders that only understand
+/// those. The pixel data is dithered as the display transform asks and stored
+/// without compression.
+pub fn write_png(
+    out: &mut impl Write,
+    image: &Image,
+    alpha: Option<&Image>,
+    display: &DisplayTransform,
+) -> io::Result<()> {
+    let space = display.output_space;
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::new();
+    header.extend((image.width() as u32).to_be_bytes());
+    header.extend((image.height() as u32).to_be_bytes());
+    let color_type = if alpha.is_some() { 6 } else { 2 }; // RGBA or RGB
+    header.extend([8, color_type, 0, 0, 0]); // 8 bits per channel, no interlacing
+    chunk(out, b"IHDR", &header)?;
+
+    if space == ColorSpace::Srgb {
+        chunk(out, b"sRGB", &[0])?; // Perceptual rendering intent
+        chunk(out, b"gAMA", &45455u32.to_be_bytes())?;
+    } else {
+        let mut icc = format!("{space:?}").into_bytes();
+        icc.extend([0, 0]); // Name terminator, then zlib compression
+        icc.extend(zlib_stored(&icc_profile(space)));
+        chunk(out, b"iCCP", &icc)?;
+    }
+    let c = space.chromaticities();
+    let mut chrm = Vec::new();
+    for (x, y) in [c.white, c.red, c.green, c.blue] {
+        chrm.extend(((x * 100000.0).round() as u32).to_be_bytes());
+        chrm.extend(((y * 100000.0).round() as u32).to_be_bytes());
+    }
+    chunk(out, b"cHRM", &chrm)?;
+
+    let channels = 3 + alpha.is_some() as usize;
+    let mut raw = Vec::with_capacity((channels * image.width() + 1) * image.height());
+    for y in 0..image.height() {
+        raw.push(0); // No filter
+        for x in 0..image.width() {
+            let byte = |v: f64, channel: usize| display.dither.quantize(v, x, y, channel);
+            let color = image.pixel(x, y).clone();
+            let Some(alpha) = alpha else {
+                let c = Vec3::from(display.encode(color));
+                raw.extend([byte(c.x, 0), byte(c.y, 1), byte(c.z, 2)]);
+                continue;
+            };
+            let a = Vec3::from(alpha.pixel(x, y).clone()).x;
+            let c = Vec3::from(display.encode(unpremultiply(color, a)));
+            raw.extend([byte(c.x, 0), byte(c.y, 1), byte(c.z, 2), byte(a, 3)]);
+        }
+    }
+    chunk(out, b"IDAT", &zlib_stored(&raw))?;
+    chunk(out, b"IEND", &[])
+}
+
+fn chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data));
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// A zlib stream holding the data in uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        stream.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let len = block.len() as u16;
+        stream.push(last);
+        stream.extend(len.to_le_bytes());
+        stream.extend((!len).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+/// A version 2 ICC display profile for the colour space: its primaries adapted to
+/// the D50 connection space, and its transfer function as a table.
+fn icc_profile(space: ColorSpace) -> Vec<u8> {
+    let s15 = |v: f64| ((v * 65536.0).round() as i32).to_be_bytes();
+    let xyz = |v: Vec3| {
+        let mut data = b"XYZ \0\0\0\0".to_vec();
+        data.extend([s15(v.x), s15(v.y), s15(v.z)].concat());
+        data
+    };
+
+    let name = format!("{space:?}");
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend((name.len() as u32 + 1).to_be_bytes());
+    desc.extend(name.as_bytes());
+    desc.push(0);
+    desc.extend([0; 4 + 4 + 2 + 1 + 67]); // Empty Unicode and ScriptCode descriptions
+    let mut cprt = b"text\0\0\0\0No copyright".to_vec();
+    cprt.push(0);
+
+    let adapted = adapted_primaries(space);
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    if space != ColorSpace::AcesCg {
+        const ENTRIES: u32 = 1024;
+        curve.extend(ENTRIES.to_be_bytes());
+        for i in 0..ENTRIES {
+            let linear = space.decode(i as f64 / (ENTRIES - 1) as f64);
+            curve.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+        }
+    } else {
+        curve.extend(0u32.to_be_bytes()); // Identity
+    }
+
+    let d50 = Vec3::new(0.9642, 1.0, 0.8249);
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", desc),
+        (b"cprt", cprt),
+        (b"wtpt", xyz(d50)),
+        (b"rXYZ", xyz(adapted[0])),
+        (b"gXYZ", xyz(adapted[1])),
+        (b"bXYZ", xyz(adapted[2])),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let first_data = 128 + 4 + 12 * tags.len();
+    for (signature, element) in &tags {
+        table.extend(*signature);
+        table.extend(((first_data + data.len()) as u32).to_be_bytes());
+        table.extend((element.len() as u32).to_be_bytes());
+        data.extend(element);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    let mut profile = Vec::new();
+    profile.extend(((first_data + data.len()) as u32).to_be_bytes());
+    profile.extend([0; 4]); // Preferred CMM
+    profile.extend(0x02100000u32.to_be_bytes());
+    profile.extend(b"mntrRGB XYZ ");
+    profile.extend([0; 12]); // Creation date
+    profile.extend(b"acsp");
+    profile.extend([0; 24]); // Platform, flags, device manufacturer, model and attributes
+    profile.extend([0; 4]); // Perceptual rendering intent
+    profile.extend([s15(d50.x), s15(d50.y), s15(d50.z)].concat());
+    profile.resize(128, 0);
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+/// The XYZ of each primary at full intensity, adapted to D50.
+fn adapted_primaries(space: ColorSpace) -> [Vec3; 3] {
+    let adapt = bradford(space.chromaticities().white, D50);
+    let to_xyz = space.rgb_to_xyz();
+    [0, 1, 2].map(|i| {
+        let primary = Vec3::new(to_xyz[0][i], to_xyz[1][i], to_xyz[2][i]);
+        transform(&adapt, primary)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::png::{adler32, crc32, zlib_stored};
+
+    #[test]
+    fn test_checksums_and_stored_blocks() {
+        assert_eq!(0xae426082, crc32(b"IEND"));
+        assert_eq!(0x11e60398, adler32(b"Wikipedia"));
+
+        let data = vec![7; 70000];
+        let stream = zlib_stored(&data);
+        assert_eq!(2 + 2 * 5 + 70000 + 4, stream.len());
+        assert_eq!([0, 0xff, 0xff, 0, 0], stream[2..7]);
+        assert_eq!(1, stream[2 + 5 + 65535]);
+    }
+}
@@ -0,0 +1,293 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, image::Image, vec3::Vec3};
+
+/// Glow around highlights from light scattering in the lens and eye.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bloom {
+    pub intensity: f64, // Fraction of the light above the threshold spread out, 0 for none
+    pub threshold: f64, // Luminance above which pixels bloom
+    pub radius: f64,    // Standard deviation in pixels of the widest of four Gaussians
+}
+
+/// Star-shaped streaks from diffraction at the aperture's diaphragm blades.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glare {
+    pub intensity: f64, // Fraction of the light above the threshold spread out, 0 for none
+    pub threshold: f64,
+    pub blades: usize, // Even counts give as many streaks, odd counts twice as many
+    pub length: f64,   // In pixels
+    pub rotation: f64, // In degrees
+}
+
+/// Effects applied to the linear image before the display transform, in the order
+/// of the fields. Each is off at its default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostEffects {
+    pub chromatic_aberration: f64, // Red and blue magnified by ± this fraction about the centre
+    pub vignetting: f64,           // Strength of the cos⁴ falloff towards the corners
+    pub bloom: Bloom,
+    pub glare: Glare,
+    pub grain: f64, // Standard deviation of the grain relative to the pixel's light
+}
+
+impl Default for PostEffects {
+    fn default() -> PostEffects {
+        PostEffects {
+            chromatic_aberration: 0.0,
+            vignetting: 0.0,
+            bloom: Bloom {
+                intensity: 0.0,
+                threshold: 1.0,
+                radius: 32.0,
+            },
+            glare: Glare {
+                intensity: 0.0,
+                threshold: 2.0,
+                blades: 6,
+                length: 48.0,
+                rotation: 0.0,
+            },
+            grain: 0.0,
+        }
+    }
+}
+
+/// An image as a flat buffer of vectors, for the arithmetic the effects need.
+struct Buffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl Buffer {
+    fn new(width: usize, height: usize) -> Buffer {
+        Buffer {
+            width,
+            height,
+            pixels: vec![Vec3::zero(); width * height],
+        }
+    }
+
+    /// Bilinear lookup with the edges extended outwards.
+    fn sample(&self, x: f64, y: f64) -> Vec3 {
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let at = |x: usize, y: usize| self.pixels[y * self.width + x];
+        (1.0 - fy) * ((1.0 - fx) * at(x0, y0) + fx * at(x1, y0))
+            + fy * ((1.0 - fx) * at(x0, y1) + fx * at(x1, y1))
+    }
+
+    /// Add to the four pixels around (x, y) in proportion to their closeness.
+    fn splat(&mut self, x: f64, y: f64, value: Vec3) {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        for (dx, dy, weight) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let (px, py) = (x0 as i64 + dx, y0 as i64 + dy);
+            if px >= 0 && py >= 0 && (px as usize) < self.width && (py as usize) < self.height {
+                self.pixels[py as usize * self.width + px as usize] += weight * value;
+            }
+        }
+    }
+
+    /// The light above `threshold` luminance, keeping each pixel's colour.
+    fn bright_pass(&self, threshold: f64) -> Buffer {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|&c| {
+                let luminance = Color::from(c).luminance();
+                if luminance > threshold {
+                    ((luminance - threshold) / luminance) * c
+                } else {
+                    Vec3::zero()
+                }
+            })
+            .collect();
+        Buffer { pixels, ..*self }
+    }
+
+    /// A separable Gaussian blur, renormalised where the kernel leaves the image.
+    fn blur(&self, sigma: f64) -> Buffer {
+        let radius = (3.0 * sigma).ceil() as i64;
+        let kernel: Vec<f64> = (-radius..=radius)
+            .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let pass = |source: &Buffer, dx: i64, dy: i64| {
+            let mut blurred = Buffer::new(source.width, source.height);
+            for y in 0..source.height as i64 {
+                for x in 0..source.width as i64 {
+                    let (mut sum, mut weight_sum) = (Vec3::zero(), 0.0);
+                    for (k, weight) in (-radius..=radius).zip(&kernel) {
+                        let (qx, qy) = (x + k * dx, y + k * dy);
+                        if qx >= 0
+                            && qy >= 0
+                            && qx < source.width as i64
+                            && qy < source.height as i64
+                        {
+                            sum +=
+                                *weight * source.pixels[(qy * source.width as i64 + qx) as usize];
+                            weight_sum += weight;
+                        }
+                    }
+                    blurred.pixels[(y * source.width as i64 + x) as usize] = sum / weight_sum;
+                }
+            }
+            blurred
+        };
+        pass(&pass(self, 1, 0), 0, 1)
+    }
+}
+
+impl PostEffects {
+    /// Apply the effects that are on. Grain is drawn from `seed`, so the same image
+    /// and seed always give the same result.
+    pub fn apply(&self, image: &Image, seed: u64) -> Image {
+        let (width, height) = (image.width(), image.height());
+        if width == 0 || height == 0 {
+            return Image::new(width, height);
+        }
+        let mut buffer = Buffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                buffer.pixels[y * width + x] = image.pixel(x, y).clone().into();
+            }
+        }
+
+        let center = Vec3::new((width - 1) as f64 / 2.0, (height - 1) as f64 / 2.0, 0.0);
+        let corner_distance = center.length().max(1.0);
+        let position = |p: usize| Vec3::new((p % width) as f64, (p / width) as f64, 0.0);
+
+        if self.chromatic_aberration != 0.0 {
+            let k = self.chromatic_aberration;
+            let source = &buffer;
+            let pixels = (0..width * height)
+                .map(|p| {
+                    let offset = position(p) - center;
+                    let red = center + (1.0 - k) * offset;
+                    let blue = center + (1.0 + k) * offset;
+                    Vec3::new(
+                        source.sample(red.x, red.y).x,
+                        source.pixels[p].y,
+                        source.sample(blue.x, blue.y).z,
+                    )
+                })
+                .collect();
+            buffer.pixels = pixels;
+        }
+
+        if self.vignetting != 0.0 {
+            for (p, c) in buffer.pixels.iter_mut().enumerate() {
+                let r = (position(p) - center).length() / corner_distance;
+                let cos2 = 1.0 / (1.0 + (self.vignetting * r).powi(2));
+                *c = (cos2 * cos2) * *c;
+            }
+        }
+
+        let bloom = &self.bloom;
+        if bloom.intensity > 0.0 {
+            let bright = buffer.bright_pass(bloom.threshold);
+            const SCALES: i32 = 4;
+            for scale in 0..SCALES {
+                let blurred = bright.blur(bloom.radius / 2f64.powi(scale));
+                for (c, glow) in buffer.pixels.iter_mut().zip(&blurred.pixels) {
+                    *c += (bloom.intensity / SCALES as f64) * *glow;
+                }
+            }
+        }
+
+        let glare = &self.glare;
+        if glare.intensity > 0.0 && glare.blades > 0 && glare.length >= 1.0 {
+            let bright = buffer.bright_pass(glare.threshold);
+            let streaks = if glare.blades.is_multiple_of(2) {
+                glare.blades
+            } else {
+                2 * glare.blades
+            };
+            let steps = glare.length.ceil() as usize;
+            let falloff: Vec<f64> = (1..=steps)
+                .map(|s| (-3.0 * s as f64 / glare.length).exp())
+                .collect();
+            let total = streaks as f64 * falloff.iter().sum::<f64>();
+            let directions: Vec<Vec3> = (0..streaks)
+                .map(|i| {
+                    let angle = glare.rotation.to_radians() + 2.0 * PI * i as f64 / streaks as f64;
+                    Vec3::new(angle.cos(), -angle.sin(), 0.0)
+                })
+                .collect();
+            for (p, &light) in bright.pixels.iter().enumerate() {
+                if light == Vec3::zero() {
+                    continue;
+                }
+                let origin = position(p);
+                for direction in &directions {
+                    for (s, weight) in falloff.iter().enumerate() {
+                        let q = origin + (s + 1) as f64 * *direction;
+                        buffer.splat(q.x, q.y, (glare.intensity * weight / total) * light);
+                    }
+                }
+            }
+        }
+
+        if self.grain > 0.0 {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            for c in &mut buffer.pixels {
+                // Box-Muller transform of two uniform samples to a normal one.
+                let normal = (-2.0 * (1.0 - rng.f64()).ln()).sqrt() * (2.0 * PI * rng.f64()).cos();
+                *c = (1.0 + self.grain * normal).max(0.0) * *c;
+            }
+        }
+
+        let mut result = Image::new(width, height);
+        for (p, &c) in buffer.pixels.iter().enumerate() {
+            result.set_pixel(p % width, p / width, c.into());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, image::Image, post::PostEffects};
+
+    #[test]
+    fn test_bloom_and_glare_spread_light_from_highlights_only() {
+        let mut image = Image::new(33, 33);
+        image.set_pixel(16, 16, Color::new(100.0, 100.0, 100.0));
+        image.set_pixel(2, 2, Color::new(0.5, 0.5, 0.5));
+
+        let mut effects = PostEffects::default();
+        assert_eq!(image.pixel(16, 16), effects.apply(&image, 0).pixel(16, 16));
+
+        effects.bloom.intensity = 0.1;
+        effects.bloom.radius = 4.0;
+        effects.glare.intensity = 0.1;
+        effects.glare.blades = 4;
+        effects.glare.length = 8.0;
+        let result = effects.apply(&image, 0);
+
+        let total = |image: &Image| {
+            let mut sum = 0.0;
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    sum += image.pixel(x, y).luminance();
+                }
+            }
+            sum
+        };
+        // Each effect adds 10% of the light above the threshold, all of which lands
+        // inside the image.
+        let excess = 99.0;
+        assert!((total(&result) - total(&image) - 0.2 * excess).abs() < 0.5);
+        // A glare streak runs along the row through the highlight, but not diagonally.
+        assert!(result.pixel(20, 16).luminance() > result.pixel(19, 19).luminance());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// A parallelogram with corner `q` and edges `u` and `v`.
+pub struct Quad<'a> {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f64,
+    mat: Box<dyn Material + 'a>,
+}
+
+impl<'a> Quad<'a> {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: impl Material + 'a) -> Quad<'a> {
+        let n = Vec3::cross(u, v);
+        let normal = Vec3::unit_vector(n);
+        Quad {
+            q,
+            u,
+            v,
+            w: n / Vec3::dot(n, n),
+            normal,
+            d: Vec3::dot(normal, q),
+            mat: Box::new(mat),
+        }
+    }
+}
+
+impl<'a> Hittable for Quad<'a> {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+        let denom = Vec3::dot(self.normal, r.direction);
+
+        // No hit if the ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - Vec3::dot(self.normal, r.origin)) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        // Express the hit point in the plane's (u, v) coordinates and reject it when
+        // it falls outside the parallelogram.
+        let p = r.at(t);
+        let planar_hitpt_vector = p - self.q;
+        let alpha = Vec3::dot(self.w, Vec3::cross(planar_hitpt_vector, self.v));
+        let beta = Vec3::dot(self.w, Vec3::cross(self.u, planar_hitpt_vector));
+
+        let unit_interval = Interval::new(0.0, 1.0);
+        if !unit_interval._contains(alpha) || !unit_interval._contains(beta) {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(p, self.normal, Vec3::unit_vector(self.u), t, &*self.mat);
+        (rec.u, rec.v) = (alpha, beta);
+        rec.set_face_normal(r);
+
+        Some(rec)
+    }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![self.mat.as_ref()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        hittable::Hittable,
+        interval::Interval,
+        material::Lambertian,
+        quad::Quad,
+        ray::Ray,
+        vec3::{Point3, Vec3},
+    };
+
+    #[test]
+    fn test_hits_only_inside_the_parallelogram() {
+        // A sheared quad in the plane z = -1 with corners (0, 0), (2, 0), (1, 1), (3, 1).
+        let quad = Quad::new(
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        );
+        let hit = |x: f64, y: f64| {
+            let r = Ray::new(Point3::new(x, y, 0.0), Vec3::new(0.0, 0.0, -1.0));
+            quad.hit(&r, Interval::new(0.001, f64::INFINITY))
+        };
+
+        let rec = hit(1.5, 0.5).unwrap();
+        assert_eq!(1.0, rec.t);
+        assert!((rec.u - 0.5).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+        assert!(hit(2.9, 0.95).is_some());
+
+        // Inside the bounding box but outside the slanted edges.
+        assert!(hit(0.2, 0.8).is_none());
+        assert!(hit(2.8, 0.2).is_none());
+        assert!(hit(-0.1, 0.5).is_none() && hit(1.5, 1.1).is_none());
+
+        // Parallel rays and hits outside the interval miss.
+        let parallel = Ray::new(Point3::new(1.0, 0.5, -1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(
+            quad.hit(&parallel, Interval::new(0.001, f64::INFINITY))
+                .is_none()
+        );
+        let r = Ray::new(Point3::new(1.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.hit(&r, Interval::new(0.001, 0.5)).is_none());
+    }
+}
@@ -0,0 +1,406 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use fastrand::Rng;
+
+use crate::blue_noise;
+
+/// A source of sample values in [0, 1) for one pixel sample at a time.
+///
+/// Every random decision made while tracing a camera sample draws the next
+/// dimension, in the same order for every sample, so samplers that spread points
+/// evenly across a pixel's samples can do so separately for each decision. The
+/// values depend only on the render seed, the pixel, the sample index and the
+/// dimension, so a render is reproducible whatever order pixels are traced in.
+pub trait Sampler {
+    /// Start sample `index` of pixel (x, y), going back to the first dimension.
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: usize);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// The sampler implementations selectable on the camera.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    /// Uncorrelated white noise.
+    #[default]
+    Independent,
+    /// Jittered strata, shuffled independently for each dimension.
+    Stratified,
+    /// The Halton sequence, randomly rotated for each pixel.
+    Halton,
+    /// The Sobol sequence with hashed Owen scrambling for each pixel and dimension.
+    Sobol,
+    /// The Sobol sequence rotated by a blue-noise tile, so the error left at low
+    /// sample counts looks like fine grain instead of blotches.
+    BlueNoise,
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SamplerKind, String> {
+        match s {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "blue-noise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!("Unknown sampler: {s}")),
+        }
+    }
+}
+
+impl SamplerKind {
+    pub fn create(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        let stream = SampleStream {
+            seed,
+            ..Default::default()
+        };
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler { stream }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                samples_per_pixel: samples_per_pixel.max(1),
+                stream,
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler { stream }),
+            SamplerKind::Sobol => Box::new(SobolSampler { stream }),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler { stream }),
+        }
+    }
+}
+
+/// Where a sampler is within the sample pattern, with a generator for white noise
+/// that restarts from a hash of the seed, pixel and sample index.
+#[derive(Default)]
+struct SampleStream {
+    seed: u64,
+    x: u64,
+    y: u64,
+    index: usize,
+    dimension: u64,
+    rng: Rng,
+}
+
+impl SampleStream {
+    fn start(&mut self, x: u64, y: u64, index: usize) {
+        (self.x, self.y, self.index, self.dimension) = (x, y, index, 0);
+        self.rng.seed(hash(&[self.seed, x, y, index as u64]));
+    }
+    /// Hash of the seed, pixel and the dimension about to be drawn, then advance by
+    /// `n` dimensions.
+    fn next_dimension(&mut self, n: u64) -> (u64, u64) {
+        let dimension = self.dimension;
+        self.dimension += n;
+        (dimension, hash(&[self.seed, self.x, self.y, dimension]))
+    }
+}
+
+pub struct IndependentSampler {
+    stream: SampleStream,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: usize) {
+        self.stream.start(x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        self.stream.rng.f64()
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.stream.rng.f64(), self.stream.rng.f64())
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    stream: SampleStream,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: usize) {
+        self.stream.start(x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let (_, seed) = self.stream.next_dimension(1);
+        let n = self.samples_per_pixel as u32;
+        let stratum = permutation_element(self.stream.index as u32 % n, n, seed as u32);
+        (stratum as f64 + self.stream.rng.f64()) / n as f64
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, seed) = self.stream.next_dimension(2);
+        // The smallest grid with at least one cell per sample.
+        let nx = (self.samples_per_pixel as f64).sqrt().ceil() as u32;
+        let ny = (self.samples_per_pixel as u32).div_ceil(nx);
+        let stratum =
+            permutation_element(self.stream.index as u32 % (nx * ny), nx * ny, seed as u32);
+        (
+            ((stratum % nx) as f64 + self.stream.rng.f64()) / nx as f64,
+            ((stratum / nx) as f64 + self.stream.rng.f64()) / ny as f64,
+        )
+    }
+}
+
+pub struct HaltonSampler {
+    stream: SampleStream,
+}
+
+impl HaltonSampler {
+    fn sample(&self, dimension: u64, rotation: u64) -> f64 {
+        let primes = primes();
+        let Some(&base) = primes.get(dimension as usize) else {
+            // Past the tabulated bases fall back to hashed white noise.
+            return unit_float(hash(&[rotation, self.stream.index as u64]) as u32);
+        };
+        let value = radical_inverse(base, self.stream.index as u64) + unit_float(rotation as u32);
+        value.fract()
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: usize) {
+        self.stream.start(x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let (dimension, seed) = self.stream.next_dimension(1);
+        self.sample(dimension, seed)
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (dimension, seed) = self.stream.next_dimension(2);
+        (
+            self.sample(dimension, seed),
+            self.sample(dimension + 1, hash(&[seed])),
+        )
+    }
+}
+
+/// Sobol points in padded form: each 1D or 2D draw uses the first Sobol dimensions,
+/// with the sample order shuffled and the digits Owen-scrambled by a hash of the
+/// pixel and dimension.
+pub struct SobolSampler {
+    stream: SampleStream,
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: usize) {
+        self.stream.start(x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let (_, seed) = self.stream.next_dimension(1);
+        let index = nested_uniform_scramble(self.stream.index as u32, seed as u32);
+        unit_float(nested_uniform_scramble(
+            sobol(index, 0),
+            (seed >> 32) as u32,
+        ))
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, seed) = self.stream.next_dimension(2);
+        let index = nested_uniform_scramble(self.stream.index as u32, seed as u32);
+        let seed_y = hash(&[seed]);
+        (
+            unit_float(nested_uniform_scramble(
+                sobol(index, 0),
+                (seed >> 32) as u32,
+            )),
+            unit_float(nested_uniform_scramble(
+                sobol(index, 1),
+                (seed_y >> 32) as u32,
+            )),
+        )
+    }
+}
+
+/// Sobol points that are the same for every pixel except for a toroidal shift read
+/// from a blue-noise tile, offset differently for every dimension.
+pub struct BlueNoiseSampler {
+    stream: SampleStream,
+}
+
+impl BlueNoiseSampler {
+    fn sample(&self, sobol_dimension: u32, dimension_seed: u64) -> f64 {
+        let index = nested_uniform_scramble(self.stream.index as u32, dimension_seed as u32);
+        let point = unit_float(nested_uniform_scramble(
+            sobol(index, sobol_dimension),
+            (dimension_seed >> 32) as u32,
+        ));
+        let (dx, dy) = (dimension_seed >> 8, dimension_seed >> 24);
+        let shift = blue_noise::value(self.stream.x + dx, self.stream.y + dy);
+        (point + shift).fract()
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, index: usize) {
+        self.stream.start(x, y, index);
+    }
+    fn get_1d(&mut self) -> f64 {
+        let (dimension, _) = self.stream.next_dimension(1);
+        self.sample(0, hash(&[self.stream.seed, dimension]))
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (dimension, _) = self.stream.next_dimension(2);
+        let seed = hash(&[self.stream.seed, dimension]);
+        (self.sample(0, seed), self.sample(1, hash(&[seed])))
+    }
+}
+
+/// Mix a list of integers into a well distributed 64-bit hash.
+pub fn hash(values: &[u64]) -> u64 {
+    let mut h = 0x243f6a8885a308d3u64;
+    for &v in values {
+        h = mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15));
+    }
+    h
+}
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn unit_float(bits: u32) -> f64 {
+    bits as f64 / 4294967296.0
+}
+
+/// Element `i` of a random permutation of `0..n` selected by `seed`, from
+/// Kensler's "Correlated Multi-Jittered Sampling".
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+/// The first 256 primes, used as Halton bases.
+fn primes() -> &'static [u64] {
+    static PRIMES: OnceLock<Vec<u64>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        let mut primes: Vec<u64> = Vec::with_capacity(256);
+        let mut candidate = 2;
+        while primes.len() < 256 {
+            if primes.iter().all(|p| candidate % p != 0) {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+        primes
+    })
+}
+
+/// The digits of `index` in `base` mirrored about the radix point.
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + (index - next * base);
+        inv_base_m *= inv_base;
+        index = next;
+    }
+    (reversed as f64 * inv_base_m).min(1.0 - f64::EPSILON)
+}
+
+/// Point `index` of the first (van der Corput) or second Sobol dimension as a
+/// 32-bit fixed point fraction.
+fn sobol(index: u32, dimension: u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    // The second dimension's generator matrix: each direction number is the previous
+    // one xor'ed with itself shifted right.
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambling of a 32-bit fraction, from Burley's "Practical Hash-based Owen
+/// Scrambling".
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut v = x.reverse_bits();
+    v = v.wrapping_add(seed);
+    v ^= v.wrapping_mul(0x6c50b47c);
+    v ^= v.wrapping_mul(0xb82f1e52);
+    v ^= v.wrapping_mul(0xc7afe638);
+    v ^= v.wrapping_mul(0x8d22f6e6);
+    v.reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampler::{Sampler, SamplerKind};
+
+    /// Each sampler's points for one pixel fill every stratum of a 4 x 4 grid when
+    /// 16 samples are taken.
+    #[test]
+    fn test_low_discrepancy_samplers_are_stratified() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.create(16, 0);
+            let mut seen = [false; 16];
+            for index in 0..16 {
+                sampler.start_pixel_sample(3, 7, index);
+                let (u, v) = sampler.get_2d();
+                seen[(u * 4.0) as usize + 4 * (v * 4.0) as usize] = true;
+            }
+            assert!(seen.iter().all(|&s| s), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_samples_depend_only_on_seed_pixel_and_index() {
+        let draw = |sampler: &mut Box<dyn Sampler>, index| {
+            sampler.start_pixel_sample(5, 2, index);
+            (sampler.get_1d(), sampler.get_2d())
+        };
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+            SamplerKind::BlueNoise,
+        ] {
+            let mut first = kind.create(8, 42);
+            let mut second = kind.create(8, 42);
+            let expected = draw(&mut first, 3);
+            draw(&mut second, 6);
+            assert_eq!(draw(&mut second, 3), expected, "{kind:?}");
+            assert_ne!(draw(&mut kind.create(8, 43), 3), expected, "{kind:?}");
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    alpha_mask::{AlphaMask, AlphaTest},
+    camera::Camera,
+    color::Color,
+    hittable_list::HittableList,
+    image::Image,
+    material::{
+        BumpMapped, Coated, Dielectric, DiffuseLight, Lambertian, Metal, MixMaterial, NormalMapped,
+        ThinFilm,
+    },
+    quad::Quad,
+    sphere::Sphere,
+    texture::{CheckerTexture, ImageTexture, SolidColor},
+    util::{random_f64, random_f64_range, seed_random},
+    vec3::{Point3, Vec3},
+};
+
+pub fn final_scene(seed: u64) -> (HittableList<'static>, Camera) {
+    seed_random(seed);
+    let mut world = HittableList::new();
+
+    let ground_material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+    world.add(Sphere::new(
+        Point3::new(0., -1000., 0.),
+        1000.,
+        ground_material,
+    ));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_f64();
+            let center = Point3::new(
+                a as f64 + 0.9 * random_f64(),
+                0.2,
+                b as f64 + 0.9 * random_f64(),
+            );
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Vec3::random() * Vec3::random();
+                    let sphere_material = Lambertian::new(albedo.into());
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Vec3::random_range(0.5, 1.0).into();
+                    let fuzz = random_f64_range(0.0, 0.5);
+                    let sphere_material = Metal::new(albedo, fuzz);
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                } else {
+                    // glass
+                    let sphere_material = Dielectric::new(1.5);
+                    world.add(Sphere::new(center, 0.2, sphere_material));
+                }
+            }
+        }
+    }
+
+    let material1 = Dielectric::new(1.5);
+    world.add(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material1));
+
+    let material2 = Lambertian::new(Color::new(0.4, 0.2, 0.1));
+    world.add(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material2));
+
+    let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
+    world.add(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material3));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 1200;
+    cam.samples_per_pixel = 500;
+    cam.max_depth = 50;
+
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 0.6;
+    cam.focus_dist = 10.0;
+
+    (world, cam)
+}
+
+pub fn three_spheres() -> (HittableList<'static>, Camera) {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.0));
+    let material_center = Lambertian::new(Color::new(0.1, 0.2, 0.5));
+    let material_left = Dielectric::new(1.50);
+    let material_bubble = ThinFilm::new(
+        Dielectric::new(1.00 / 1.50),
+        380.0,
+        1.33 / 1.50,
+        1.00 / 1.50,
+    );
+    let material_right = Metal::new(Color::new(0.8, 0.6, 0.2), 1.0);
+
+    world.add(Sphere::new(
+        Point3::new(0., -100.5, -1.),
+        100.,
+        material_ground,
+    ));
+    world.add(Sphere::new(Point3::new(0., 0., -1.2), 0.5, material_center));
+    world.add(Sphere::new(Point3::new(-1.0, 0., -1.0), 0.5, material_left));
+    world.add(Sphere::new(
+        Point3::new(-1.0, 0., -1.0),
+        0.4,
+        material_bubble,
+    ));
+    world.add(Sphere::new(Point3::new(1.0, 0., -1.0), 0.5, material_right));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(-2.0, 2.0, 1.0);
+    cam.lookat = Point3::new(0.0, 0.0, -1.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    cam.defocus_angle = 10.0;
+    cam.focus_dist = 3.4;
+
+    (world, cam)
+}
+
+pub fn layered_materials() -> (HittableList<'static>, Camera) {
+    let mut world = HittableList::new();
+
+    let checker = CheckerTexture::new(
+        0.5,
+        SolidColor::new(Color::new(0.2, 0.3, 0.1)),
+        SolidColor::new(Color::new(0.9, 0.9, 0.9)),
+    );
+    let material_ground = MixMaterial::textured(
+        Lambertian::new(Color::new(0.8, 0.8, 0.8)),
+        Metal::new(Color::new(0.8, 0.8, 0.8), 0.1),
+        checker,
+    );
+    let material_paint = Coated::new(Metal::new(Color::new(0.6, 0.05, 0.05), 0.4), 1.5);
+    let material_wood = Coated::new(Lambertian::new(Color::new(0.45, 0.25, 0.1)), 1.5);
+    let material_blend = MixMaterial::new(
+        Lambertian::new(Color::new(0.1, 0.2, 0.5)),
+        Metal::new(Color::new(0.8, 0.8, 0.8), 0.0),
+        0.3,
+    );
+
+    world.add(Sphere::new(
+        Point3::new(0., -100.5, -1.),
+        100.,
+        material_ground,
+    ));
+    world.add(Sphere::new(
+        Point3::new(-1.0, 0., -1.0),
+        0.5,
+        material_paint,
+    ));
+    world.add(Sphere::new(Point3::new(0., 0., -1.2), 0.5, material_wood));
+    world.add(Sphere::new(Point3::new(1.0, 0., -1.0), 0.5, material_blend));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(-2.0, 2.0, 1.0);
+    cam.lookat = Point3::new(0.0, 0.0, -1.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+/// Bump-mapped tiles on the left and, when a PPM normal map path is given, a
+/// normal-mapped sphere on the right.
+pub fn mapped_spheres(
+    normal_map_path: Option<&str>,
+) -> Result<(HittableList<'static>, Camera), String> {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.0));
+    let tiles = CheckerTexture::new(
+        0.1,
+        SolidColor::new(Color::new(0.0, 0.0, 0.0)),
+        SolidColor::new(Color::new(1.0, 1.0, 1.0)),
+    );
+    let material_left = BumpMapped::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.2), tiles, 0.002);
+    let material_center = Lambertian::new(Color::new(0.1, 0.2, 0.5));
+
+    world.add(Sphere::new(
+        Point3::new(0., -100.5, -1.),
+        100.,
+        material_ground,
+    ));
+    world.add(Sphere::new(Point3::new(-1.0, 0., -1.0), 0.5, material_left));
+    world.add(Sphere::new(Point3::new(0., 0., -1.2), 0.5, material_center));
+
+    if let Some(path) = normal_map_path {
+        let normal_map =
+            Image::load_ppm(path).map_err(|e| format!("Cannot load normal map {path}: {e}"))?;
+        let material_right = NormalMapped::new(
+            Lambertian::new(Color::new(0.8, 0.6, 0.2)),
+            ImageTexture::new(normal_map),
+        );
+        world.add(Sphere::new(Point3::new(1.0, 0., -1.0), 0.5, material_right));
+    }
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(-2.0, 2.0, 1.0);
+    cam.lookat = Point3::new(0.0, 0.0, -1.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    Ok((world, cam))
+}
+
+/// A chain-link style fence cut out of a single quad in front of the spheres, and a
+/// stochastically transparent curtain behind them.
+pub fn cutout_fence() -> (HittableList<'static>, Camera) {
+    let mut world = HittableList::new();
+
+    let material_ground = Lambertian::new(Color::new(0.8, 0.8, 0.0));
+    let material_center = Lambertian::new(Color::new(0.1, 0.2, 0.5));
+    let material_right = Metal::new(Color::new(0.8, 0.6, 0.2), 0.3);
+    let material_fence = Metal::new(Color::new(0.6, 0.6, 0.6), 0.5);
+
+    world.add(Sphere::new(
+        Point3::new(0., -100.5, -1.),
+        100.,
+        material_ground,
+    ));
+    world.add(Sphere::new(Point3::new(0., 0., -1.2), 0.5, material_center));
+    world.add(Sphere::new(Point3::new(1.0, 0., -1.0), 0.5, material_right));
+
+    let links = CheckerTexture::new(
+        0.04,
+        SolidColor::new(Color::new(0.0, 0.0, 0.0)),
+        SolidColor::new(Color::new(1.0, 1.0, 1.0)),
+    );
+    let fence = Quad::new(
+        Point3::new(-1.5, -0.5, -0.3),
+        Vec3::new(3.0, 0.0, -0.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        material_fence,
+    );
+    world.add(AlphaMask::new(fence, links, AlphaTest::Threshold(0.5)));
+
+    // A sheer curtain that lets half of the rays through.
+    let curtain = Quad::new(
+        Point3::new(-1.6, -0.5, -1.6),
+        Vec3::new(0.0, 0.0, 1.0),
+        Vec3::new(0.0, 1.2, 0.0),
+        Lambertian::new(Color::new(0.9, 0.9, 0.9)),
+    );
+    let sheer = SolidColor::new(Color::new(0.5, 0.5, 0.5));
+    world.add(AlphaMask::new(curtain, sheer, AlphaTest::Stochastic));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 100;
+    cam.max_depth = 50;
+    cam.vfov = 20.;
+    cam.lookfrom = Point3::new(-2.0, 2.0, 1.0);
+    cam.lookat = Point3::new(0.0, 0.0, -1.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+
+    (world, cam)
+}
+
+/// Spheres under a dim sky lit by a warm key light and a cool fill light, each in
+/// its own light group.
+pub fn light_groups() -> (HittableList<'static>, Camera) {
+    let mut world = HittableList::new();
+
+    world.add(Sphere::new(
+        Point3::new(0., -100.5, -1.),
+        100.,
+        Lambertian::new(Color::new(0.6, 0.6, 0.6)),
+    ));
+    world.add(Sphere::new(
+        Point3::new(0., 0., -1.2),
+        0.5,
+        Lambertian::new(Color::new(0.1, 0.2, 0.5)),
+    ));
+    world.add(Sphere::new(
+        Point3::new(-1.0, 0., -1.0),
+        0.5,
+        Dielectric::new(1.5),
+    ));
+    world.add(Sphere::new(
+        Point3::new(1.0, 0., -1.0),
+        0.5,
+        Metal::new(Color::new(0.8, 0.6, 0.2), 0.2),
+    ));
+    world.add(Sphere::new(
+        Point3::new(-0.5, 3.0, 0.5),
+        0.5,
+        DiffuseLight::new(Color::new(8.0, 6.0, 4.0), 1),
+    ));
+    world.add(Sphere::new(
+        Point3::new(2.5, 1.0, -3.0),
+        0.5,
+        DiffuseLight::new(Color::new(2.0, 3.0, 6.0), 2),
+    ));
+
+    let mut cam = Camera::new();
+    cam.aspect_ratio = 16.0 / 9.0;
+    cam.image_width = 400;
+    cam.samples_per_pixel = 200;
+    cam.max_depth = 50;
+    cam.vfov = 30.;
+    cam.lookfrom = Point3::new(-2.0, 2.0, 2.0);
+    cam.lookat = Point3::new(0.0, 0.0, -1.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.sky_intensity = 0.1;
+    cam.light_groups = vec![
+        String::from("sky"),
+        String::from("key"),
+        String::from("fill"),
+    ];
+
+    (world, cam)
+}
@@ -20,6 +20,29 @@ impl<'a> Sphere<'a> {
             mat: Box::new(mat),
         }
     }
+
+    /// Map a point on the unit sphere to texture coordinates, with u in [0, 1] around
+    /// the Y axis starting from X=-1 and v in [0, 1] from Y=-1 to Y=+1.
+    fn get_sphere_uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + std::f64::consts::PI;
+
+        (
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        )
+    }
+
+    /// Unit tangent in the direction of increasing u for a point on the unit sphere.
+    fn get_sphere_tangent(p: Point3) -> Vec3 {
+        let tangent = Vec3::new(p.z, 0.0, -p.x);
+        if tangent.near_zero() {
+            // At the poles any horizontal direction will do.
+            Vec3::new(1.0, 0.0, 0.0)
+        } else {
+            Vec3::unit_vector(tangent)
+        }
+    }
 }
 
 impl<'a> Hittable for Sphere<'a> {
@@ -49,9 +72,15 @@ impl<'a> Hittable for Sphere<'a> {
         let t = root;
         let p = r.at(t);
         let normal = (p - self.center) / self.radius;
-        let mut rec = HitRecord::new(p, normal, t, &*self.mat);
+        let tangent = Sphere::get_sphere_tangent(normal);
+        let mut rec = HitRecord::new(p, normal, tangent, t, &*self.mat);
+        (rec.u, rec.v) = Sphere::get_sphere_uv(normal);
         rec.set_face_normal(r);
 
         Some(rec)
     }
+
+    fn materials(&self) -> Vec<&dyn Material> {
+        vec![self.mat.as_ref()]
+    }
 }
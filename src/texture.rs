@@ -0,0 +1,74 @@
+use crate::{color::Color, image::Image, interval::Interval, vec3::Point3};
+
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> SolidColor {
+        SolidColor { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        self.albedo.clone()
+    }
+}
+
+/// A 3D checker pattern alternating between two textures.
+pub struct CheckerTexture<'a> {
+    inv_scale: f64,
+    even: Box<dyn Texture + 'a>,
+    odd: Box<dyn Texture + 'a>,
+}
+
+impl<'a> CheckerTexture<'a> {
+    pub fn new(scale: f64, even: impl Texture + 'a, odd: impl Texture + 'a) -> CheckerTexture<'a> {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even: Box::new(even),
+            odd: Box::new(odd),
+        }
+    }
+}
+
+impl<'a> Texture for CheckerTexture<'a> {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let x = (self.inv_scale * p.x).floor() as i64;
+        let y = (self.inv_scale * p.y).floor() as i64;
+        let z = (self.inv_scale * p.z).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Looks up the nearest pixel of an image at (u, v), with v = 0 at the bottom row.
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> ImageTexture {
+        ImageTexture { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        let u = Interval::new(0.0, 1.0).clamp(u);
+        let v = 1.0 - Interval::new(0.0, 1.0).clamp(v);
+
+        let i = ((u * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let j = ((v * self.image.height() as f64) as usize).min(self.image.height() - 1);
+        self.image.pixel(i, j).clone()
+    }
+}
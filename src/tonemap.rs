@@ -0,0 +1,288 @@
+use std::str::FromStr;
+
+use crate::{
+    color::Color,
+    colorspace::{ColorSpace, Matrix, transform},
+    dither::Dither,
+    lut::Lut,
+    vec3::Vec3,
+};
+
+/// Curves that compress the scene's range of light into the display's.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum ToneMapper {
+    /// No compression; everything above the white point burns out.
+    #[default]
+    Clamp,
+    /// x / (1 + x), which approaches white without reaching it.
+    Reinhard,
+    /// Reinhard stretched so the white point reaches white.
+    ExtendedReinhard,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+    /// Stephen Hill's fit of the ACES reference and sRGB output transforms.
+    Aces,
+    /// Troy Sobotka's AgX, which desaturates bright colours towards white.
+    Agx,
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ToneMapper, String> {
+        match s {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "extended-reinhard" => Ok(ToneMapper::ExtendedReinhard),
+            "hable" => Ok(ToneMapper::Hable),
+            "aces" => Ok(ToneMapper::Aces),
+            "agx" => Ok(ToneMapper::Agx),
+            _ => Err(format!("Unknown tone mapper: {s}")),
+        }
+    }
+}
+
+impl ToneMapper {
+    /// The scene value shown as white when no white point is given, if the curve
+    /// needs one.
+    fn default_white_point(&self) -> Option<f64> {
+        match self {
+            ToneMapper::Clamp => Some(1.0),
+            ToneMapper::ExtendedReinhard => Some(4.0),
+            ToneMapper::Hable => Some(11.2),
+            ToneMapper::Reinhard | ToneMapper::Aces | ToneMapper::Agx => None,
+        }
+    }
+
+    /// The curve applied to light in `space`. The per-channel curves work on its
+    /// primaries directly, while ACES and AgX convert to and from the primaries
+    /// they were designed for.
+    fn curve(&self, c: Vec3, white_point: f64, space: ColorSpace) -> Vec3 {
+        let c = Vec3::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0));
+        let each = |f: &dyn Fn(f64) -> f64| Vec3::new(f(c.x), f(c.y), f(c.z));
+        match self {
+            ToneMapper::Clamp => c,
+            ToneMapper::Reinhard => each(&|x| x / (1.0 + x)),
+            ToneMapper::ExtendedReinhard => {
+                let w2 = white_point * white_point;
+                each(&|x| x * (1.0 + x / w2) / (1.0 + x))
+            }
+            ToneMapper::Hable => each(&hable),
+            ToneMapper::Aces => {
+                let srgb = transform(&space.conversion_to(ColorSpace::Srgb), c);
+                let v = transform(&ACES_INPUT, srgb);
+                let fitted = Vec3::new(rrt_and_odt(v.x), rrt_and_odt(v.y), rrt_and_odt(v.z));
+                let mapped = transform(&ACES_OUTPUT, fitted);
+                transform(&ColorSpace::Srgb.conversion_to(space), mapped)
+            }
+            ToneMapper::Agx => agx(c, space),
+        }
+    }
+}
+
+/// Turns the linear scene light in the framebuffer, in the working space, into
+/// display light in the output space.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DisplayTransform {
+    pub exposure: f64,            // In stops; each one doubles the light
+    pub white_point: Option<f64>, // Exposed scene value shown as white, or the curve's default
+    pub tone_mapper: ToneMapper,
+    pub working_space: ColorSpace, // The space colours in the scene are given in
+    pub output_space: ColorSpace,
+    pub lut: Option<Lut>, // A grade applied to the encoded colour
+    pub dither: Dither,   // Noise added when quantising to 8 bits
+}
+
+impl DisplayTransform {
+    /// Linear display light in [0, 1].
+    pub fn apply(&self, color: Color) -> Color {
+        let color = color.convert(self.working_space, self.output_space);
+        let c = self.exposure.exp2() * Vec3::from(color);
+        let white_point = self.white_point.or(self.tone_mapper.default_white_point());
+        let space = self.output_space;
+        let mapped = self.tone_mapper.curve(c, white_point.unwrap_or(1.0), space);
+        let scale = match (white_point, self.tone_mapper) {
+            (Some(w), ToneMapper::Clamp | ToneMapper::Reinhard | ToneMapper::Hable) => {
+                1.0 / self.tone_mapper.curve(Vec3::new(w, w, w), w, space).x
+            }
+            // The RGB curves map grey to slightly different levels per channel.
+            (Some(w), ToneMapper::Aces | ToneMapper::Agx) => {
+                let grey = self.tone_mapper.curve(Vec3::new(w, w, w), w, space);
+                1.0 / Color::from(grey).luminance()
+            }
+            // Extended Reinhard reaches white at the white point by construction.
+            (_, ToneMapper::ExtendedReinhard) | (None, _) => 1.0,
+        };
+        let clamp = |x: f64| (scale * x).clamp(0.0, 1.0);
+        Color::new(clamp(mapped.x), clamp(mapped.y), clamp(mapped.z))
+    }
+
+    /// Display light passed through the output space's transfer function and any
+    /// grading LUT, ready to be quantised.
+    pub fn encode(&self, color: Color) -> Color {
+        let c = Vec3::from(self.apply(color));
+        let encode = |x: f64| self.output_space.encode(x);
+        let encoded = Color::new(encode(c.x), encode(c.y), encode(c.z));
+        match &self.lut {
+            Some(lut) => lut.apply(encoded),
+            None => encoded,
+        }
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15; // Shoulder strength
+    const B: f64 = 0.50; // Linear strength
+    const C: f64 = 0.10; // Linear angle
+    const D: f64 = 0.20; // Toe strength
+    const E: f64 = 0.02; // Toe numerator
+    const F: f64 = 0.30; // Toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+/// sRGB to the ACES rendering space, with the reference transform's exposure and
+/// saturation tweaks folded in.
+const ACES_INPUT: Matrix = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+const ACES_OUTPUT: Matrix = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn rrt_and_odt(v: f64) -> f64 {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    a / b
+}
+
+const AGX_INSET: Matrix = [
+    [0.856627153315983, 0.0951212405381588, 0.0482516061458583],
+    [0.137318972929847, 0.761241990602591, 0.101439036467562],
+    [0.11189821299995, 0.0767994186031903, 0.811302368396859],
+];
+
+const AGX_OUTSET: Matrix = [
+    [
+        1.1271005818144368,
+        -0.11060664309660323,
+        -0.016493938717834573,
+    ],
+    [
+        -0.1413297634984383,
+        1.157823702216272,
+        -0.016493938717834257,
+    ],
+    [
+        -0.14132976349843826,
+        -0.11060664309660294,
+        1.2519364065950405,
+    ],
+];
+
+/// AgX in Rec. 2020: squeeze the primaries inwards, take a log encoding of about
+/// 16.5 stops through a sigmoid, and widen the primaries again. Light comes in and
+/// goes out in `space`.
+fn agx(c: Vec3, space: ColorSpace) -> Vec3 {
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+    let rec2020 = transform(&space.conversion_to(ColorSpace::Rec2020), c);
+    let inset = transform(&AGX_INSET, rec2020);
+    let encode = |x: f64| ((x.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV)).clamp(0.0, 1.0);
+    // A polynomial fit of the default contrast sigmoid.
+    let sigmoid = |x: f64| {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+    let curved = Vec3::new(
+        sigmoid(encode(inset.x)),
+        sigmoid(encode(inset.y)),
+        sigmoid(encode(inset.z)),
+    );
+    // The sigmoid's output is display encoded; undo the 2.2 gamma it assumes.
+    let outset = transform(&AGX_OUTSET, curved);
+    let linear = |x: f64| x.max(0.0).powf(2.2);
+    transform(
+        &ColorSpace::Rec2020.conversion_to(space),
+        Vec3::new(linear(outset.x), linear(outset.y), linear(outset.z)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        colorspace::{ColorSpace, transform},
+        tonemap::{DisplayTransform, ToneMapper},
+        vec3::Vec3,
+    };
+
+    const TONE_MAPPERS: [ToneMapper; 6] = [
+        ToneMapper::Clamp,
+        ToneMapper::Reinhard,
+        ToneMapper::ExtendedReinhard,
+        ToneMapper::Hable,
+        ToneMapper::Aces,
+        ToneMapper::Agx,
+    ];
+
+    #[test]
+    fn test_tone_mappers_are_monotonic_and_reach_white_at_the_white_point() {
+        for tone_mapper in TONE_MAPPERS {
+            let display = DisplayTransform {
+                exposure: 1.0,
+                white_point: Some(8.0),
+                tone_mapper,
+                ..Default::default()
+            };
+            let level = |x: f64| display.apply(Color::new(x, x, x)).luminance();
+            assert!(level(0.0) < 0.01, "{tone_mapper:?}");
+            assert!((level(4.0) - 1.0).abs() < 0.01, "{tone_mapper:?}");
+            let mut previous = 0.0;
+            for i in 1..=40 {
+                let next = level(i as f64 * 0.1);
+                assert!(next >= previous, "{tone_mapper:?} at {i}");
+                previous = next;
+            }
+        }
+    }
+
+    #[test]
+    fn test_tone_mapping_keeps_its_look_in_every_output_space() {
+        for tone_mapper in TONE_MAPPERS {
+            let apply = |output_space: ColorSpace, color: Color| {
+                let display = DisplayTransform {
+                    tone_mapper,
+                    output_space,
+                    ..Default::default()
+                };
+                let light = Vec3::from(display.apply(color));
+                (light, transform(&output_space.rgb_to_xyz(), light))
+            };
+            let same = |a: ColorSpace, b: ColorSpace, color: Color| {
+                let (_, expected) = apply(a, color.clone());
+                let (_, actual) = apply(b, color);
+                let message = format!("{tone_mapper:?} {a:?} {b:?}: {actual:?} vs {expected:?}");
+                assert!((actual - expected).length() < 1e-3, "{message}");
+            };
+            for space in [ColorSpace::Srgb, ColorSpace::DisplayP3, ColorSpace::Rec2020] {
+                let (grey, _) = apply(space, Color::new(0.3, 0.3, 0.3));
+                assert!((grey.x - grey.y).abs() < 1e-3, "{tone_mapper:?} {space:?}");
+                assert!((grey.z - grey.y).abs() < 1e-3, "{tone_mapper:?} {space:?}");
+            }
+
+            // The per-channel curves depend on the primaries by design, but the
+            // others should give the same light in any space that can show it.
+            if [ToneMapper::Clamp, ToneMapper::Aces, ToneMapper::Agx].contains(&tone_mapper) {
+                let (wide, other) = (ColorSpace::Rec2020, ColorSpace::DisplayP3);
+                same(wide, other, Color::new(0.6, 0.0, 0.0));
+                same(wide, ColorSpace::Srgb, Color::new(0.4, 0.2, 0.1));
+            }
+        }
+    }
+}
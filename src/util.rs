@@ -1,3 +1,9 @@
+/// Restart the generator behind `random_f64` from `seed`, for procedural scenes
+/// that should come out the same every run.
+pub fn seed_random(seed: u64) {
+    fastrand::seed(seed);
+}
+
 pub fn random_f64() -> f64 {
     fastrand::f64()
 }
@@ -1,4 +1,4 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
 
 use crate::util::{random_f64, random_f64_range};
 pub type Point3 = Vec3;
@@ -58,12 +58,34 @@ impl Vec3 {
             }
         }
     }
+    /// Map a point of the unit square uniformly onto the unit sphere.
+    pub fn sample_unit_sphere(u: (f64, f64)) -> Vec3 {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * u.1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+    /// Map a point of the unit square uniformly onto the unit disk, keeping nearby
+    /// points nearby (Shirley and Chiu's concentric mapping).
+    pub fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+        let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::zero();
+        }
+        let quarter_pi = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, quarter_pi * (b / a))
+        } else {
+            (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
     pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
         let on_unit_sphere = Vec3::random_unit_vector();
         if Vec3::dot(on_unit_sphere, normal) > 0.0 {
-            return on_unit_sphere;
+            on_unit_sphere
         } else {
-            return -on_unit_sphere;
+            -on_unit_sphere
         }
     }
 
@@ -101,6 +123,12 @@ impl Add for Vec3 {
     }
 }
 
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
 impl Mul for Vec3 {
     type Output = Vec3;
     fn mul(self, other: Vec3) -> Vec3 {